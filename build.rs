@@ -0,0 +1,20 @@
+//! Compiling the gRPC service protobuf definitions by the pure Rust `protox`
+//! compiler, so the build does not depend on the `protoc` installation.
+
+use prost::Message;
+use std::{env, fs, path::PathBuf};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let descriptor_path =
+    PathBuf::from(env::var("OUT_DIR")?).join("facts_descriptor.bin");
+  let file_descriptors = protox::compile(["facts.proto"], ["proto"])?;
+  fs::write(&descriptor_path, file_descriptors.encode_to_vec())?;
+
+  tonic_build::configure()
+    .file_descriptor_set_path(&descriptor_path)
+    .skip_protoc_run()
+    .compile(&["proto/facts.proto"], &["proto"])?;
+
+  println!("cargo:rerun-if-changed=proto");
+  Ok(())
+}
@@ -15,9 +15,130 @@ pub struct ConfigData {
 }
 
 /// API server configuration parameters
-#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ServerConfig {
   pub address: String,
+  #[serde(default)]
+  pub stream: StreamConfig,
+  #[serde(default)]
+  pub websocket: WebSocketConfig,
+  /// Serving the Swagger UI documentation page at `/docs`
+  #[serde(default)]
+  pub docs: bool,
+  #[serde(default)]
+  pub graphql: GraphQLConfig,
+  #[serde(default)]
+  pub grpc: GrpcConfig,
+}
+
+impl Default for ServerConfig {
+  fn default() -> Self {
+    ServerConfig {
+      address: "0.0.0.0:8888".into(),
+      stream: StreamConfig::default(),
+      websocket: WebSocketConfig::default(),
+      docs: false,
+      graphql: GraphQLConfig::default(),
+      grpc: GrpcConfig::default(),
+    }
+  }
+}
+
+/// Server-Sent Events fact stream parameters, all intervals are in seconds
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StreamConfig {
+  /// Lowest interval between the facts a client can request
+  pub min_interval: u64,
+  /// Highest interval between the facts a client can request
+  pub max_interval: u64,
+  /// Interval used when a client does not provide one
+  pub default_interval: u64,
+  /// Interval of the keep-alive comments sent to the client
+  pub keep_alive: u64,
+}
+
+impl Default for StreamConfig {
+  fn default() -> Self {
+    StreamConfig {
+      min_interval: 1,
+      max_interval: 3600,
+      default_interval: 10,
+      keep_alive: 15,
+    }
+  }
+}
+
+/// WebSocket fact subscriptions parameters. Subscription intervals are bounded
+/// by the `StreamConfig` intervals.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct WebSocketConfig {
+  /// Maximum number of animals a single connection can be subscribed to
+  pub max_subscriptions: usize,
+  /// Interval of the ping frames sent to the client in seconds
+  pub ping_interval: u64,
+  /// Connection is closed when a ping was not answered within this many
+  /// seconds
+  pub pong_timeout: u64,
+  /// Maximum number of the `get` commands of a single connection fetching the
+  /// facts at the same time, the excess ones are rejected
+  pub max_pending_gets: usize,
+}
+
+impl Default for WebSocketConfig {
+  fn default() -> Self {
+    WebSocketConfig {
+      max_subscriptions: 8,
+      ping_interval: 30,
+      pong_timeout: 60,
+      max_pending_gets: 4,
+    }
+  }
+}
+
+/// GraphQL API parameters
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct GraphQLConfig {
+  /// Serving the GraphQL API at `/graphql`
+  pub enabled: bool,
+  /// Serving the GraphiQL playground at `GET /graphql`
+  pub playground: bool,
+  /// Maximum number of facts requested by a single `facts` query
+  pub max_count: u32,
+}
+
+impl Default for GraphQLConfig {
+  fn default() -> Self {
+    GraphQLConfig {
+      enabled: false,
+      playground: false,
+      max_count: 10,
+    }
+  }
+}
+
+/// gRPC API parameters
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct GrpcConfig {
+  /// Serving the gRPC API next to the HTTP server
+  pub enabled: bool,
+  /// Listening address of the gRPC server in `IP:PORT` format
+  pub address: String,
+  /// Maximum number of facts requested by a single `GetFacts` call
+  pub max_count: u32,
+}
+
+impl Default for GrpcConfig {
+  fn default() -> Self {
+    GrpcConfig {
+      enabled: false,
+      address: "0.0.0.0:50051".into(),
+      max_count: 10,
+    }
+  }
 }
 
 impl ConfigData {
@@ -47,13 +168,15 @@ mod tests {
     let valid_config_data = ConfigData {
       server: ServerConfig {
         address: "0.0.0.0:8888".into(),
+        ..Default::default()
       },
       animals: FactsConfig {
         default: "dog".into(),
         facts: HashMap::from([(
           "dog".to_string(),
-          "http://some-dog-endpoint".to_string(),
+          "http://some-dog-endpoint".into(),
         )]),
+        ..Default::default()
       },
     };
     let plain_text_config = serde_json::to_string(&valid_config_data).unwrap();
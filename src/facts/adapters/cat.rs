@@ -3,18 +3,37 @@
 //! API endpoint documentation: https://alexwohlbruck.github.io/cat-facts/docs/endpoints/facts.html
 //! Implementation of the `Transformable` trait for the adapter abstraction.
 
-use crate::facts::Transformable;
+use crate::facts::{Fact, ResponseContext, Transformable};
 use anyhow::bail;
 use serde::{Deserialize, Serialize};
 use tracing::{instrument, trace};
 
 /// Endpoint JSON response structure
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct CatFactEndpointResponse {
   pub text: String,
   #[serde(rename = "type")]
   pub animal_type: String,
   pub deleted: bool,
+  #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  #[serde(
+    rename = "createdAt",
+    default,
+    skip_serializing_if = "Option::is_none"
+  )]
+  pub created_at: Option<String>,
+  /// Id of the user submitted the fact
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub user: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub status: Option<CatFactStatus>,
+}
+
+/// Verification status of the fact
+#[derive(Default, Serialize, Deserialize)]
+pub struct CatFactStatus {
+  pub verified: Option<bool>,
 }
 
 #[derive(Debug)]
@@ -22,10 +41,14 @@ pub struct Adapter;
 impl Transformable for Adapter {
   /// Transforms the endpoint response into the expected fact format
   #[instrument]
-  fn transform(&self, response_content: &str) -> anyhow::Result<String> {
+  async fn transform(
+    &self,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
     trace!("transforming fact content from cat endpoint response");
 
-    let data: CatFactEndpointResponse = serde_json::from_str(response_content)?;
+    let data: CatFactEndpointResponse =
+      serde_json::from_str(response.success_text()?)?;
     if data.deleted {
       bail!("cat fact endpoint returned `deleted`=true on the fact")
     }
@@ -35,7 +58,17 @@ impl Transformable for Adapter {
         data.animal_type
       )
     }
-    Ok(data.text)
+    let verified = data.status.and_then(|status| status.verified);
+    Ok(vec![Fact {
+      id: data.id,
+      created_at: data.created_at,
+      attribution: data.user,
+      tags: match verified {
+        Some(true) => vec!["verified".to_string()],
+        _ => Vec::new(),
+      },
+      ..Fact::new(data.text)
+    }])
   }
 }
 
@@ -43,29 +76,71 @@ impl Transformable for Adapter {
 mod tests {
   use super::*;
 
+  /// URL the test responses are served from
+  const URL: &str = "http://localhost/facts";
+
   /// Test transformation by passing input data from an API endpoint output and
   /// expect the fact should be extracted
-  #[test]
-  fn transforming_valid() {
+  #[tokio::test]
+  async fn transforming_valid() {
     // Valid input data to test
     let fact = "some cat fact";
     let plain_text_input = serde_json::to_string(&CatFactEndpointResponse {
       text: fact.to_string(),
       animal_type: "cat".to_string(),
       deleted: false,
+      ..Default::default()
+    })
+    .unwrap();
+    let transformed = Adapter
+      .transform(&ResponseContext::new(URL, plain_text_input))
+      .await
+      .unwrap();
+
+    assert_eq!(transformed[0].text, fact, "transformed fact are not equal");
+  }
+
+  /// Test the metadata of the fact is taken from the endpoint response
+  #[tokio::test]
+  async fn transforming_metadata() {
+    let plain_text_input = serde_json::to_string(&CatFactEndpointResponse {
+      text: "some cat fact".to_string(),
+      animal_type: "cat".to_string(),
+      id: Some("58e008780aac31001185ed05".to_string()),
+      created_at: Some("2018-03-29T20:20:03.844Z".to_string()),
+      user: Some("58e007480aac31001185ecef".to_string()),
+      status: Some(CatFactStatus {
+        verified: Some(true),
+      }),
+      ..Default::default()
     })
     .unwrap();
-    let transformed = Adapter.transform(&plain_text_input).unwrap();
+    let transformed = Adapter
+      .transform(&ResponseContext::new(URL, plain_text_input))
+      .await
+      .unwrap()
+      .remove(0);
 
-    assert_eq!(transformed, fact, "transformed fact are not equal");
+    assert_eq!(transformed.id.as_deref(), Some("58e008780aac31001185ed05"));
+    assert_eq!(
+      transformed.created_at.as_deref(),
+      Some("2018-03-29T20:20:03.844Z")
+    );
+    assert_eq!(
+      transformed.attribution.as_deref(),
+      Some("58e007480aac31001185ecef")
+    );
+    assert_eq!(transformed.tags, vec!["verified"]);
   }
 
   /// Test transformation by passing invalid input (not json) and expecting an error
-  #[test]
-  fn transforming_invalid() {
+  #[tokio::test]
+  async fn transforming_invalid() {
     // Invalid input data to test
     let plain_text_input = "just a plain text, not json";
-    let transformed = Adapter.transform(plain_text_input);
+    let transformed = Adapter
+      .transform(&ResponseContext::new(URL, plain_text_input))
+      .await;
 
     assert!(transformed.is_err());
   }
@@ -0,0 +1,113 @@
+//! Adapter for CSV endpoint responses
+//!
+//! The facts are the values of the column of the `CsvConfig`, selected by the
+//! header name or the index.
+//! Implementation of the `Transformable` trait for the adapter abstraction.
+
+use crate::facts::config::{CsvColumn, CsvConfig};
+use crate::facts::{Fact, ResponseContext, Transformable};
+use anyhow::{anyhow, bail};
+use tracing::{instrument, trace};
+
+#[derive(Debug)]
+pub struct Adapter<'a> {
+  config: &'a CsvConfig,
+}
+
+impl<'a> Adapter<'a> {
+  pub fn new(config: &'a CsvConfig) -> Self {
+    Adapter { config }
+  }
+}
+
+impl Transformable for Adapter<'_> {
+  /// Transform the endpoint response into the expected fact format
+  #[instrument(skip(response))]
+  async fn transform(
+    &self,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
+    trace!("transforming fact content from the CSV response");
+
+    if !self.config.delimiter.is_ascii() {
+      bail!("CSV delimiter is not ASCII: {}", self.config.delimiter)
+    }
+    let mut reader = ::csv::ReaderBuilder::new()
+      .delimiter(self.config.delimiter as u8)
+      .has_headers(self.config.has_headers)
+      .flexible(true)
+      .from_reader(response.success_text()?.as_bytes());
+    let index = match &self.config.column {
+      CsvColumn::Index(index) => *index,
+      CsvColumn::Name(name) => reader
+        .headers()?
+        .iter()
+        .position(|header| header.trim() == name)
+        .ok_or_else(|| anyhow!("CSV response has no column: {}", name))?,
+    };
+
+    let mut facts = Vec::new();
+    for record in reader.records() {
+      match record?.get(index).map(str::trim) {
+        Some(value) if !value.is_empty() => facts.push(Fact::new(value)),
+        _ => {}
+      }
+    }
+    Ok(facts)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// URL the test responses are served from
+  const URL: &str = "http://localhost/facts.csv";
+
+  /// Test selecting the column by the header name and by the index
+  #[tokio::test]
+  async fn transforming_valid() {
+    let body = "id,fact\n1,first cat fact\n2,\"second, quoted cat fact\"\n3,\n";
+    let config = CsvConfig {
+      column: CsvColumn::Name("fact".to_string()),
+      delimiter: ',',
+      has_headers: true,
+    };
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, body))
+      .await
+      .unwrap();
+    assert_eq!(
+      transformed,
+      vec![
+        Fact::new("first cat fact"),
+        Fact::new("second, quoted cat fact")
+      ]
+    );
+
+    let config = CsvConfig {
+      column: CsvColumn::Index(1),
+      delimiter: ';',
+      has_headers: false,
+    };
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, "1;first cat fact"))
+      .await
+      .unwrap();
+    assert_eq!(transformed, vec![Fact::new("first cat fact")]);
+  }
+
+  /// Test the unknown column name
+  #[tokio::test]
+  async fn transforming_invalid() {
+    let config = CsvConfig {
+      column: CsvColumn::Name("text".to_string()),
+      delimiter: ',',
+      has_headers: true,
+    };
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, "id,fact\n1,first cat fact\n"))
+      .await;
+    assert!(transformed.is_err());
+  }
+}
@@ -3,7 +3,8 @@
 //! API documentation: https://kinduff.github.io/dog-api/
 //! Implementation of the `Transformable` trait for the adapter abstraction.
 
-use crate::facts::Transformable;
+use super::NoFacts;
+use crate::facts::{Fact, ResponseContext, Transformable};
 use anyhow::bail;
 use serde::{Deserialize, Serialize};
 use tracing::{instrument, trace};
@@ -20,31 +21,36 @@ pub struct Adapter;
 impl Transformable for Adapter {
   /// Transform the endpoint response into the expected fact format
   #[instrument]
-  fn transform(&self, response_content: &str) -> anyhow::Result<String> {
+  async fn transform(
+    &self,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
     trace!("transforming fact content from the dog response");
 
-    let data: DogFactEndpointResponse = serde_json::from_str(response_content)?;
+    let data: DogFactEndpointResponse =
+      serde_json::from_str(response.success_text()?)?;
     if !data.success {
       bail!("dog fact endpoint returned `success`=false on the fact")
     }
-    if data.facts.len() > 1 {
-      bail!(
-        "dog fact endpoint returned more than one fact result: {}",
-        data.facts.len()
-      )
+    if data.facts.is_empty() {
+      return Err(NoFacts.into());
     }
-    Ok(data.facts[0].clone())
+    Ok(data.facts.into_iter().map(Fact::new).collect())
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use reqwest::StatusCode;
+
+  /// URL the test responses are served from
+  const URL: &str = "http://localhost/facts";
 
   /// Test transformation by passing input data from API endpoint format and
   /// expect the fact should be extracted
-  #[test]
-  fn transforming_valid() {
+  #[tokio::test]
+  async fn transforming_valid() {
     // Valid input data to test
     let dog_fact = "some dog fact";
     let plain_text_input = serde_json::to_string(&DogFactEndpointResponse {
@@ -52,18 +58,71 @@ mod tests {
       success: true,
     })
     .unwrap();
-    let transformed = Adapter.transform(&plain_text_input).unwrap();
+    let transformed = Adapter
+      .transform(&ResponseContext::new(URL, plain_text_input))
+      .await
+      .unwrap();
 
-    assert_eq!(transformed, dog_fact, "transformed fact is not equal");
+    assert_eq!(
+      transformed,
+      vec![Fact::new(dog_fact)],
+      "facts are not equal"
+    );
   }
 
   /// Test transformation by passing invalid input (not json) and expecting an error
-  #[test]
-  fn transforming_invalid() {
+  #[tokio::test]
+  async fn transforming_invalid() {
     // Invalid input data to test
     let plain_text_input = "just a plain text, not json";
-    let transformed = Adapter.transform(plain_text_input);
+    let transformed = Adapter
+      .transform(&ResponseContext::new(URL, plain_text_input))
+      .await;
+
+    assert!(transformed.is_err());
+  }
+
+  /// Test transformation of the non-successful response with the valid body
+  /// and expecting an error
+  #[tokio::test]
+  async fn transforming_failed_response() {
+    let plain_text_input = serde_json::to_string(&DogFactEndpointResponse {
+      facts: vec!["some dog fact".to_string()],
+      success: true,
+    })
+    .unwrap();
+    let response = ResponseContext {
+      status: StatusCode::TOO_MANY_REQUESTS,
+      ..ResponseContext::new(URL, plain_text_input)
+    };
+    let transformed = Adapter.transform(&response).await;
 
     assert!(transformed.is_err());
   }
+
+  /// Test transformation of the response with several facts or none
+  #[tokio::test]
+  async fn transforming_multiple() {
+    let plain_text_input = serde_json::to_string(&DogFactEndpointResponse {
+      facts: vec!["first dog fact".to_string(), "second dog fact".to_string()],
+      success: true,
+    })
+    .unwrap();
+    let transformed = Adapter
+      .transform(&ResponseContext::new(URL, plain_text_input))
+      .await
+      .unwrap();
+    assert_eq!(transformed.len(), 2);
+
+    let plain_text_input = serde_json::to_string(&DogFactEndpointResponse {
+      facts: Vec::new(),
+      success: true,
+    })
+    .unwrap();
+    let err = Adapter
+      .transform(&ResponseContext::new(URL, plain_text_input))
+      .await
+      .unwrap_err();
+    assert_eq!(err.downcast_ref::<NoFacts>(), Some(&NoFacts));
+  }
 }
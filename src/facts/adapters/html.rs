@@ -0,0 +1,107 @@
+//! Adapter for HTML page endpoint responses
+//!
+//! The facts are the texts of the elements matched by the CSS selector of the
+//! `HtmlConfig`, with the whitespace collapsed.
+//! Implementation of the `Transformable` trait for the adapter abstraction.
+
+use crate::facts::config::HtmlConfig;
+use crate::facts::{Fact, ResponseContext, Transformable};
+use anyhow::anyhow;
+use scraper::{Html, Selector};
+use tracing::{instrument, trace};
+
+#[derive(Debug)]
+pub struct Adapter<'a> {
+  config: &'a HtmlConfig,
+}
+
+impl<'a> Adapter<'a> {
+  pub fn new(config: &'a HtmlConfig) -> Self {
+    Adapter { config }
+  }
+
+  /// Texts of the elements matched by the selector
+  fn extract(&self, text: &str) -> anyhow::Result<Vec<String>> {
+    let selector = Selector::parse(&self.config.selector).map_err(|err| {
+      anyhow!("invalid CSS selector {}: {}", self.config.selector, err)
+    })?;
+    let document = Html::parse_document(text);
+    let texts = document
+      .select(&selector)
+      .map(|element| {
+        let text: String = element.text().collect();
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+      })
+      .collect();
+    Ok(texts)
+  }
+}
+
+impl Transformable for Adapter<'_> {
+  /// Transform the endpoint response into the expected fact format
+  #[instrument(skip(response))]
+  async fn transform(
+    &self,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
+    trace!("transforming fact content from the HTML response");
+
+    let texts = self.extract(response.success_text()?)?;
+    Ok(
+      texts
+        .into_iter()
+        .filter(|text| !text.is_empty())
+        .map(Fact::new)
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// URL the test responses are served from
+  const URL: &str = "http://localhost/facts.html";
+
+  /// Test extracting the texts of the matched elements
+  #[tokio::test]
+  async fn transforming_valid() {
+    let config = HtmlConfig {
+      selector: "ul.facts > li".to_string(),
+    };
+    let body = r#"<html><body>
+      <ul class="menu"><li>Home</li></ul>
+      <ul class="facts">
+        <li>Dogs can <b>smell</b>
+          feelings</li>
+        <li>Dogs dream</li>
+      </ul>
+    </body></html>"#;
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, body))
+      .await
+      .unwrap();
+
+    assert_eq!(
+      transformed,
+      vec![
+        Fact::new("Dogs can smell feelings"),
+        Fact::new("Dogs dream")
+      ]
+    );
+  }
+
+  /// Test the invalid CSS selector
+  #[tokio::test]
+  async fn transforming_invalid() {
+    let config = HtmlConfig {
+      selector: "ul >".to_string(),
+    };
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, "<ul><li>fact</li></ul>"))
+      .await;
+
+    assert!(transformed.is_err());
+  }
+}
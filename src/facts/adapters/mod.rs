@@ -1,7 +1,86 @@
 //! Adapters for transforming facts endpoint result into the our server expected
-//! format. Adapters should extract the fact and return it as a string.
+//! format. Adapters should extract the facts of the response and return them
+//! as the `Fact` list.
 //!
 //! Adapters should implement the `Transformable` trait from the `facts` parent module.
 
+use super::Fact;
+use serde::Deserialize;
+use std::fmt;
+
 pub mod cat;
+pub mod csv;
 pub mod dog;
+pub mod html;
+pub mod script;
+pub mod text;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod xml;
+
+/// Error of the endpoint response without any facts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoFacts;
+
+impl fmt::Display for NoFacts {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "fact endpoint returned no facts")
+  }
+}
+
+impl std::error::Error for NoFacts {}
+
+/// Fact returned by the script and plugin adapters as the object
+#[derive(Deserialize)]
+struct FactObject {
+  text: String,
+  id: Option<String>,
+  source: Option<String>,
+  created_at: Option<String>,
+  language: Option<String>,
+  #[serde(default)]
+  tags: Vec<String>,
+  attribution: Option<String>,
+}
+
+/// Fact returned by the script and plugin adapters as the text or the object
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FactItem {
+  Text(String),
+  Object(FactObject),
+}
+
+impl From<FactItem> for Fact {
+  fn from(item: FactItem) -> Self {
+    match item {
+      FactItem::Text(text) => Fact::new(text),
+      FactItem::Object(object) => Fact {
+        text: object.text,
+        id: object.id,
+        source: object.source,
+        created_at: object.created_at,
+        language: object.language,
+        tags: object.tags,
+        attribution: object.attribution,
+      },
+    }
+  }
+}
+
+/// Facts returned by the script and plugin adapters, a single one or a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FactOutput {
+  One(FactItem),
+  Many(Vec<FactItem>),
+}
+
+impl FactOutput {
+  fn into_facts(self) -> Vec<Fact> {
+    match self {
+      FactOutput::One(item) => vec![item.into()],
+      FactOutput::Many(items) => items.into_iter().map(Fact::from).collect(),
+    }
+  }
+}
@@ -0,0 +1,228 @@
+//! Adapter running the Rhai script of the `ScriptConfig`
+//!
+//! Lets the odd endpoint formats be handled without a server release. The
+//! script is read on every response, so its edits apply right away. It gets
+//! the `body`, `status`, `url`, `headers` and, for the JSON responses, the
+//! parsed `json` variables and returns the fact text, an object with the
+//! `text` and the `Fact` metadata fields, or an array of them. Throwing
+//! rejects the response.
+//!
+//! Scripts are sandboxed: there is no file or network access, `eval` and the
+//! module imports are disabled and the operations, call depth and sizes of the
+//! created strings, arrays and maps are limited. The memory of the created
+//! values is capped overall: the sizes of a value include the nested values,
+//! and the script holds at most `MAX_VARIABLES` values besides the temporary
+//! ones of the expression being evaluated. The input variables are not counted.
+//! Implementation of the `Transformable` trait for the adapter abstraction.
+
+use super::FactOutput;
+use crate::facts::config::ScriptConfig;
+use crate::facts::{Fact, ResponseContext, Transformable};
+use anyhow::anyhow;
+use rhai::{
+  module_resolvers::DummyModuleResolver, Dynamic, Engine, Map, Scope,
+};
+use tracing::{debug, instrument, trace};
+
+/// Maximum depth of the function calls of the script
+const MAX_CALL_LEVELS: usize = 32;
+
+/// Maximum number of the variables of the script, including the parameters of
+/// the function calls in progress
+const MAX_VARIABLES: usize = 256;
+
+/// Maximum number of the functions the script defines
+const MAX_FUNCTIONS: usize = 64;
+
+#[derive(Debug)]
+pub struct Adapter<'a> {
+  config: &'a ScriptConfig,
+}
+
+impl<'a> Adapter<'a> {
+  pub fn new(config: &'a ScriptConfig) -> Self {
+    Adapter { config }
+  }
+
+  /// Sandboxed script engine with the configured limits
+  fn engine(config: &ScriptConfig) -> Engine {
+    let mut engine = Engine::new();
+    engine
+      .set_max_operations(config.max_operations)
+      .set_max_call_levels(MAX_CALL_LEVELS)
+      .set_max_variables(MAX_VARIABLES)
+      .set_max_functions(MAX_FUNCTIONS)
+      .set_max_string_size(config.max_string_size)
+      .set_max_array_size(config.max_collection_size)
+      .set_max_map_size(config.max_collection_size)
+      // The default resolver imports the modules from any file path
+      .set_module_resolver(DummyModuleResolver::new())
+      .set_max_modules(0)
+      .disable_symbol("eval")
+      .on_print(|text| debug!("adapter script: {}", text))
+      .on_debug(|text, _, _| debug!("adapter script: {}", text));
+    engine
+  }
+
+  /// Running the script on the response
+  fn run(
+    config: &ScriptConfig,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
+    let script = std::fs::read_to_string(&config.path)?;
+    let body = response.success_text()?;
+    let headers: Map = response
+      .headers
+      .iter()
+      .filter_map(|(name, value)| {
+        Some((name.as_str().into(), value.to_str().ok()?.into()))
+      })
+      .collect();
+    let json = match serde_json::from_str::<serde_json::Value>(body) {
+      Ok(json) => rhai::serde::to_dynamic(json)
+        .map_err(|err| anyhow!("adapter script input error: {}", err))?,
+      Err(_) => Dynamic::UNIT,
+    };
+
+    let mut scope = Scope::new();
+    scope
+      .push_constant("body", body.to_string())
+      .push_constant("status", response.status.as_u16() as i64)
+      .push_constant("url", response.url.clone())
+      .push_constant("headers", headers)
+      .push_constant("json", json);
+    let output: Dynamic = Self::engine(config)
+      .eval_with_scope(&mut scope, &script)
+      .map_err(|err| {
+        anyhow!("adapter script {} failed: {}", config.path, err)
+      })?;
+    let output: FactOutput =
+      rhai::serde::from_dynamic(&output).map_err(|err| {
+        anyhow!("adapter script {} invalid result: {}", config.path, err)
+      })?;
+    Ok(output.into_facts())
+  }
+}
+
+impl Transformable for Adapter<'_> {
+  /// Transform the endpoint response into the expected fact format
+  #[instrument(skip(response))]
+  async fn transform(
+    &self,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
+    trace!("transforming fact content by the adapter script");
+
+    // Scripts may run up to the operations limit, so they are run off the
+    // async runtime threads
+    let (config, response) = (self.config.clone(), response.clone());
+    tokio::task::spawn_blocking(move || Self::run(&config, &response)).await?
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+  use tempfile::NamedTempFile;
+
+  /// URL the test responses are served from
+  const URL: &str = "http://localhost/facts";
+
+  /// Script file with the source
+  fn script(source: &str) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(source.as_bytes()).unwrap();
+    file
+  }
+
+  /// Adapter configuration of the script file with the default limits
+  fn script_config(file: &NamedTempFile) -> ScriptConfig {
+    serde_json::from_value(serde_json::json!({
+      "path": file.path().to_str().unwrap()
+    }))
+    .unwrap()
+  }
+
+  /// Test the script returning the text, the objects and the arrays
+  #[tokio::test]
+  async fn transforming_valid() {
+    let body = r#"{"data": [{"fact": "first dog fact", "id": 1}]}"#;
+    let file = script(
+      r#"
+        let item = json.data[0];
+        if status != 200 { throw "unexpected status"; }
+        [item.fact, #{ text: body.len().to_string(), id: item.id.to_string() }]
+      "#,
+    );
+    let config = script_config(&file);
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, body))
+      .await
+      .unwrap();
+
+    assert_eq!(transformed[0], Fact::new("first dog fact"));
+    assert_eq!(transformed[1].text, body.len().to_string());
+    assert_eq!(transformed[1].id.as_deref(), Some("1"));
+
+    let file = script(r#"let fact = body.split(";")[1]; fact.trim(); fact"#);
+    let config = script_config(&file);
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, "skipped; plain fact "))
+      .await
+      .unwrap();
+    assert_eq!(transformed, vec![Fact::new("plain fact")]);
+  }
+
+  /// Test the thrown error and the invalid result
+  #[tokio::test]
+  async fn transforming_invalid() {
+    for source in [r#"throw "no facts here""#, "42", "let x = "] {
+      let file = script(source);
+      let config = script_config(&file);
+      let transformed = Adapter::new(&config)
+        .transform(&ResponseContext::new(URL, "body"))
+        .await;
+      assert!(transformed.is_err(), "{}", source);
+    }
+  }
+
+  /// Test the scripts exceeding the operations and the size limits
+  #[tokio::test]
+  async fn sandbox_limits() {
+    let mut module =
+      tempfile::Builder::new().suffix(".rhai").tempfile().unwrap();
+    module
+      .write_all(br#"export const fact = "imported fact";"#)
+      .unwrap();
+    let import = format!(
+      r#"import "{}" as m; m::fact"#,
+      module.path().to_str().unwrap()
+    );
+    // Every string is within the size limit, but not all of them together
+    let variables = (0..1000).fold(
+      r#"let s = "fact"; while s.len() < 512 { s += s; }"#.to_string(),
+      |source, n| format!("{} let v{} = s + {};", source, n, n),
+    ) + " s";
+    for source in [
+      "loop { }",
+      r#"let s = "fact"; loop { s += s; }"#,
+      "let a = []; loop { a.push(1); }",
+      "let a = []; loop { a = [a, a]; }",
+      r#"eval("40 + 2")"#,
+      &import,
+      &variables,
+    ] {
+      let file = script(source);
+      let config = ScriptConfig {
+        max_string_size: 1024,
+        max_collection_size: 100,
+        ..script_config(&file)
+      };
+      let transformed = Adapter::new(&config)
+        .transform(&ResponseContext::new(URL, "body"))
+        .await;
+      assert!(transformed.is_err(), "{}", source);
+    }
+  }
+}
@@ -0,0 +1,107 @@
+//! Adapter for plain text endpoint responses
+//!
+//! The whole response or its lines are the facts, depending on the
+//! `TextConfig` of the source.
+//! Implementation of the `Transformable` trait for the adapter abstraction.
+
+use crate::facts::config::TextConfig;
+use crate::facts::{Fact, ResponseContext, Transformable};
+use anyhow::anyhow;
+use tracing::{instrument, trace};
+
+#[derive(Debug)]
+pub struct Adapter<'a> {
+  config: &'a TextConfig,
+}
+
+impl<'a> Adapter<'a> {
+  pub fn new(config: &'a TextConfig) -> Self {
+    Adapter { config }
+  }
+
+  /// The line trimmed when configured, `None` for the empty one
+  fn fact(&self, line: &str) -> Option<Fact> {
+    let line = if self.config.trim { line.trim() } else { line };
+    (!line.trim().is_empty()).then(|| Fact::new(line))
+  }
+}
+
+impl Transformable for Adapter<'_> {
+  /// Transform the endpoint response into the expected fact format
+  #[instrument(skip(response))]
+  async fn transform(
+    &self,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
+    trace!("transforming fact content from the plain text response");
+
+    let text = response.success_text()?;
+    if let Some(index) = self.config.line {
+      let line = text.lines().nth(index).ok_or_else(|| {
+        anyhow!("plain text response has no line with the index: {}", index)
+      })?;
+      return Ok(self.fact(line).into_iter().collect());
+    }
+    if self.config.lines {
+      return Ok(text.lines().filter_map(|line| self.fact(line)).collect());
+    }
+    Ok(self.fact(text).into_iter().collect())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// URL the test responses are served from
+  const URL: &str = "http://localhost/facts";
+
+  /// Transforming the response according to the configuration
+  async fn transform(config: TextConfig, body: &'static str) -> Vec<String> {
+    Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, body))
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|fact| fact.text)
+      .collect()
+  }
+
+  /// Test the whole response, every line and a single line are the facts
+  #[tokio::test]
+  async fn transforming_valid() {
+    let body = "  first fact \n\nsecond fact\n";
+    assert_eq!(
+      transform(TextConfig::default(), body).await,
+      vec!["first fact \n\nsecond fact"]
+    );
+    let lines = TextConfig {
+      lines: true,
+      ..Default::default()
+    };
+    assert_eq!(
+      transform(lines, body).await,
+      vec!["first fact", "second fact"]
+    );
+    let line = TextConfig {
+      line: Some(2),
+      ..Default::default()
+    };
+    assert_eq!(transform(line, body).await, vec!["second fact"]);
+  }
+
+  /// Test the missing line and the empty response
+  #[tokio::test]
+  async fn transforming_invalid() {
+    let config = TextConfig {
+      line: Some(5),
+      ..Default::default()
+    };
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, "single line"))
+      .await;
+    assert!(transformed.is_err());
+
+    assert!(transform(TextConfig::default(), " \n ").await.is_empty());
+  }
+}
@@ -0,0 +1,281 @@
+//! Adapter running the WebAssembly plugin of the `WasmConfig`
+//!
+//! Lets the adapters be shipped independently of the server. The plugin module
+//! implements the transform ABI:
+//!  - it exports its `memory`, `alloc(len: i32) -> i32` returning the address
+//!    of `len` bytes for the input and `transform(ptr: i32, len: i32) -> i64`,
+//!  - the input is the JSON `{"status", "url", "headers", "body"}` of the
+//!    endpoint response,
+//!  - `transform` returns the address of the JSON output in the high and its
+//!    length in the low 32 bits. The output is `{"facts": [...]}` with the fact
+//!    texts or objects with the `text` and the `Fact` metadata fields, or
+//!    `{"error": "..."}` rejecting the response.
+//!
+//! Plugins are sandboxed: modules importing anything are rejected, every
+//! transform runs in a fresh instance with the fuel and memory limits.
+//! Compiled modules are cached until the file changes.
+//! Implementation of the `Transformable` trait for the adapter abstraction.
+
+use super::FactOutput;
+use crate::facts::config::WasmConfig;
+use crate::facts::{Fact, ResponseContext, Transformable};
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  fmt, fs,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::SystemTime,
+};
+use tracing::{instrument, trace};
+use wasmtime::{
+  Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+/// Compiled module with the modification time and length of its file
+struct LoadedModule {
+  modified: SystemTime,
+  len: u64,
+  module: Module,
+}
+
+/// Plugin runtime with the compiled modules shared between the resolver clones
+#[derive(Clone)]
+pub struct Plugins {
+  engine: Engine,
+  modules: Arc<Mutex<HashMap<PathBuf, LoadedModule>>>,
+}
+
+impl fmt::Debug for Plugins {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Plugins").finish_non_exhaustive()
+  }
+}
+
+impl Default for Plugins {
+  fn default() -> Self {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    Plugins {
+      engine: Engine::new(&config).expect("wasm engine creating error"),
+      modules: Arc::default(),
+    }
+  }
+}
+
+impl Plugins {
+  /// Compiled module of the file, compiling it again when it has changed
+  fn module(&self, path: &Path) -> anyhow::Result<Module> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?;
+    let len = metadata.len();
+
+    let mut modules = self.modules.lock().unwrap();
+    let loaded = modules.get(path);
+    if loaded.map_or(true, |m| m.modified != modified || m.len != len) {
+      trace!("compiling the plugin module: {}", path.display());
+      let module = Module::from_file(&self.engine, path)?;
+      modules.insert(
+        path.to_path_buf(),
+        LoadedModule {
+          modified,
+          len,
+          module,
+        },
+      );
+    }
+    Ok(modules[path].module.clone())
+  }
+}
+
+/// Plugin input of the endpoint response
+#[derive(Serialize)]
+struct PluginInput<'a> {
+  status: u16,
+  url: &'a str,
+  headers: HashMap<&'a str, &'a str>,
+  body: &'a str,
+}
+
+/// Plugin output with the facts or the error
+#[derive(Deserialize)]
+struct PluginOutput {
+  facts: Option<FactOutput>,
+  error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Adapter<'a> {
+  config: &'a WasmConfig,
+  plugins: &'a Plugins,
+}
+
+impl<'a> Adapter<'a> {
+  pub fn new(config: &'a WasmConfig, plugins: &'a Plugins) -> Self {
+    Adapter { config, plugins }
+  }
+
+  /// Running the plugin transform on the response
+  fn run(
+    plugins: &Plugins,
+    config: &WasmConfig,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
+    let module = plugins.module(Path::new(&config.path))?;
+    let input = serde_json::to_vec(&PluginInput {
+      status: response.status.as_u16(),
+      url: &response.url,
+      headers: response
+        .headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+        .collect(),
+      body: response.success_text()?,
+    })?;
+
+    let limits = StoreLimitsBuilder::new()
+      .memory_size(config.max_memory)
+      .instances(1)
+      .build();
+    let mut store = Store::new(&plugins.engine, limits);
+    store.limiter(|limits: &mut StoreLimits| limits);
+    store.set_fuel(config.fuel)?;
+    let failed = |err| anyhow!("plugin {} failed: {:#}", config.path, err);
+
+    let instance = Instance::new(&mut store, &module, &[]).map_err(failed)?;
+    let memory = instance
+      .get_memory(&mut store, "memory")
+      .ok_or_else(|| anyhow!("plugin {} exports no memory", config.path))?;
+    let alloc = instance
+      .get_typed_func::<i32, i32>(&mut store, "alloc")
+      .map_err(failed)?;
+    let transform = instance
+      .get_typed_func::<(i32, i32), i64>(&mut store, "transform")
+      .map_err(failed)?;
+
+    let len = i32::try_from(input.len())?;
+    let ptr = alloc.call(&mut store, len).map_err(failed)?;
+    memory
+      .write(&mut store, ptr as u32 as usize, &input)
+      .map_err(|err| failed(err.into()))?;
+    let result = transform.call(&mut store, (ptr, len)).map_err(failed)? as u64;
+    let (ptr, len) = ((result >> 32) as usize, (result as u32) as usize);
+    let output = memory.data(&store).get(ptr..ptr + len).ok_or_else(|| {
+      anyhow!("plugin {} output is out of bounds", config.path)
+    })?;
+
+    match serde_json::from_slice(output)? {
+      PluginOutput {
+        error: Some(err), ..
+      } => bail!("plugin {} rejected the response: {}", config.path, err),
+      PluginOutput {
+        facts: Some(facts), ..
+      } => Ok(facts.into_facts()),
+      _ => bail!("plugin {} returned neither facts nor error", config.path),
+    }
+  }
+}
+
+impl Transformable for Adapter<'_> {
+  /// Transform the endpoint response into the expected fact format
+  #[instrument(skip(response))]
+  async fn transform(
+    &self,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
+    trace!("transforming fact content by the plugin");
+
+    // Compiling and running the plugin is CPU bound, so it is done off the
+    // async runtime threads
+    let (plugins, config, response) =
+      (self.plugins.clone(), self.config.clone(), response.clone());
+    tokio::task::spawn_blocking(move || Self::run(&plugins, &config, &response))
+      .await?
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+  use tempfile::NamedTempFile;
+
+  /// URL the test responses are served from
+  const URL: &str = "http://localhost/facts";
+
+  /// Plugin in the text format with the `transform` function body returning
+  /// the output written at the address `0`
+  fn plugin(output: &str, memory: u32, transform: &str) -> NamedTempFile {
+    let source = format!(
+      r#"(module
+        (memory (export "memory") {memory})
+        (global $next (mut i32) (i32.const 1024))
+        (data (i32.const 0) "{data}")
+        (func (export "alloc") (param $len i32) (result i32)
+          (local $ptr i32)
+          (local.set $ptr (global.get $next))
+          (global.set $next (i32.add (global.get $next) (local.get $len)))
+          (local.get $ptr))
+        (func (export "transform") (param $ptr i32) (param $len i32)
+          (result i64)
+          {transform}))"#,
+      data = output.replace('"', "\\\""),
+      transform = transform.replace("{len}", &output.len().to_string()),
+    );
+    let mut file = tempfile::Builder::new().suffix(".wat").tempfile().unwrap();
+    file.write_all(source.as_bytes()).unwrap();
+    file
+  }
+
+  /// Transforming the response by the plugin with the default limits
+  async fn transform(file: &NamedTempFile) -> anyhow::Result<Vec<Fact>> {
+    let config = WasmConfig {
+      path: file.path().to_str().unwrap().to_string(),
+      fuel: 10_000,
+      max_memory: 1024 * 1024,
+    };
+    let plugins = Plugins::default();
+    Adapter::new(&config, &plugins)
+      .transform(&ResponseContext::new(URL, "body"))
+      .await
+  }
+
+  /// Test the plugin returning the fact texts and objects
+  #[tokio::test]
+  async fn transforming_valid() {
+    let file = plugin(
+      r#"{"facts": ["first wasm fact", {"text": "second wasm fact", "id": "2"}]}"#,
+      1,
+      "(i64.const {len})",
+    );
+    let transformed = transform(&file).await.unwrap();
+
+    assert_eq!(transformed[0], Fact::new("first wasm fact"));
+    assert_eq!(transformed[1].text, "second wasm fact");
+    assert_eq!(transformed[1].id.as_deref(), Some("2"));
+  }
+
+  /// Test the plugin rejecting the response, exceeding the fuel and memory
+  /// limits, importing functions or returning the output out of bounds
+  #[tokio::test]
+  async fn transforming_invalid() {
+    let output = r#"{"error": "no facts"}"#;
+    let err = transform(&plugin(output, 1, "(i64.const {len})")).await;
+    assert!(err.unwrap_err().to_string().contains("no facts"));
+
+    for file in [
+      plugin(output, 1, "(loop $forever (br $forever)) (i64.const {len})"),
+      plugin(output, 64, "(i64.const {len})"),
+      plugin(output, 1, "(i64.const 0x0000_ffff_0000_0001)"),
+    ] {
+      assert!(transform(&file).await.is_err());
+    }
+
+    let mut file = tempfile::Builder::new().suffix(".wat").tempfile().unwrap();
+    file
+      .write_all(br#"(module (import "env" "fetch" (func)))"#)
+      .unwrap();
+    assert!(transform(&file).await.is_err());
+  }
+}
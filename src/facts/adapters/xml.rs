@@ -0,0 +1,109 @@
+//! Adapter for XML endpoint responses like RSS feeds
+//!
+//! The facts are the string values of the nodes matched by the XPath
+//! expression of the `XmlConfig`.
+//! Implementation of the `Transformable` trait for the adapter abstraction.
+
+use crate::facts::config::XmlConfig;
+use crate::facts::{Fact, ResponseContext, Transformable};
+use sxd_xpath::Value;
+use tracing::{instrument, trace};
+
+#[derive(Debug)]
+pub struct Adapter<'a> {
+  config: &'a XmlConfig,
+}
+
+impl<'a> Adapter<'a> {
+  pub fn new(config: &'a XmlConfig) -> Self {
+    Adapter { config }
+  }
+
+  /// String values of the nodes matched by the XPath expression
+  fn extract(&self, text: &str) -> anyhow::Result<Vec<String>> {
+    let package = sxd_document::parser::parse(text)?;
+    let document = package.as_document();
+    let values = match sxd_xpath::evaluate_xpath(&document, &self.config.xpath)?
+    {
+      Value::Nodeset(nodes) => nodes
+        .document_order()
+        .iter()
+        .map(|node| node.string_value())
+        .collect(),
+      value => vec![value.string()],
+    };
+    Ok(values)
+  }
+}
+
+impl Transformable for Adapter<'_> {
+  /// Transform the endpoint response into the expected fact format
+  #[instrument(skip(response))]
+  async fn transform(
+    &self,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
+    trace!("transforming fact content from the XML response");
+
+    let values = self.extract(response.success_text()?)?;
+    Ok(
+      values
+        .iter()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .map(Fact::new)
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// URL the test responses are served from
+  const URL: &str = "http://localhost/facts.xml";
+
+  /// Test extracting the facts of the RSS feed items
+  #[tokio::test]
+  async fn transforming_valid() {
+    let config = XmlConfig {
+      xpath: "/rss/channel/item/description".to_string(),
+    };
+    let body = r#"<?xml version="1.0"?>
+      <rss version="2.0"><channel>
+        <title>Dog facts</title>
+        <item><description> first dog fact </description></item>
+        <item><description><![CDATA[second dog fact]]></description></item>
+      </channel></rss>"#;
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, body))
+      .await
+      .unwrap();
+
+    assert_eq!(
+      transformed,
+      vec![Fact::new("first dog fact"), Fact::new("second dog fact")]
+    );
+  }
+
+  /// Test the malformed XML and the invalid XPath expression
+  #[tokio::test]
+  async fn transforming_invalid() {
+    let config = XmlConfig {
+      xpath: "//fact".to_string(),
+    };
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, "<facts><fact>unclosed"))
+      .await;
+    assert!(transformed.is_err());
+
+    let config = XmlConfig {
+      xpath: "//[".to_string(),
+    };
+    let transformed = Adapter::new(&config)
+      .transform(&ResponseContext::new(URL, "<facts/>"))
+      .await;
+    assert!(transformed.is_err());
+  }
+}
@@ -0,0 +1,448 @@
+//! Endpoint authentication
+//!
+//! Applying the `AuthConfig` of the source to the endpoint requests. The OAuth2
+//! access tokens are requested by the client credentials grant from the token
+//! URL and cached until shortly before they expire or until the endpoint
+//! rejects them.
+
+use super::config::{AuthConfig, KeyLocation};
+use super::template::{render, TemplateVars};
+use anyhow::{anyhow, bail};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use std::{
+  collections::HashMap,
+  fmt,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+use tracing::trace;
+
+/// Tokens expiring sooner are refreshed before the request
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Lifetime of the tokens the token URL responds without the `expires_in`
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(3600);
+
+/// Authentication of the endpoint request with the rendered secrets
+#[derive(Clone, PartialEq)]
+pub enum Auth {
+  ApiKey {
+    name: String,
+    value: String,
+    location: KeyLocation,
+  },
+  Basic {
+    username: String,
+    password: String,
+  },
+  Bearer {
+    token_file: String,
+  },
+  OAuth2 {
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    scope: Option<String>,
+  },
+}
+
+/// Only the scheme is printed to keep the secrets out of the logs
+impl fmt::Debug for Auth {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let scheme = match self {
+      Auth::ApiKey { .. } => "ApiKey",
+      Auth::Basic { .. } => "Basic",
+      Auth::Bearer { .. } => "Bearer",
+      Auth::OAuth2 { .. } => "OAuth2",
+    };
+    f.debug_tuple("Auth").field(&scheme).finish()
+  }
+}
+
+impl Auth {
+  /// Rendering the templates of the authentication configuration
+  pub fn render(
+    config: &AuthConfig,
+    vars: &TemplateVars,
+  ) -> anyhow::Result<Self> {
+    Ok(match config {
+      AuthConfig::ApiKey {
+        name,
+        value,
+        location,
+      } => Auth::ApiKey {
+        name: name.clone(),
+        value: render(value, vars)?,
+        location: *location,
+      },
+      AuthConfig::Basic { username, password } => Auth::Basic {
+        username: render(username, vars)?,
+        password: render(password, vars)?,
+      },
+      AuthConfig::Bearer { token_file } => Auth::Bearer {
+        token_file: render(token_file, vars)?,
+      },
+      AuthConfig::OAuth2 {
+        token_url,
+        client_id,
+        client_secret,
+        scope,
+      } => Auth::OAuth2 {
+        token_url: render(token_url, vars)?,
+        client_id: render(client_id, vars)?,
+        client_secret: render(client_secret, vars)?,
+        scope: scope.clone(),
+      },
+    })
+  }
+
+  /// Credentials of the request, the same for the requests authenticated
+  /// alike. It contains the secrets, so it's only used hashed.
+  pub fn identity(&self) -> String {
+    match self {
+      Auth::ApiKey {
+        name,
+        value,
+        location,
+      } => format!("api_key {:?} {}={}", location, name, value),
+      Auth::Basic { username, password } => {
+        format!("basic {}:{}", username, password)
+      }
+      Auth::Bearer { token_file } => format!("bearer {}", token_file),
+      Auth::OAuth2 {
+        token_url,
+        client_id,
+        client_secret,
+        scope,
+      } => format!(
+        "oauth2 {} {}:{} {}",
+        token_url,
+        client_id,
+        client_secret,
+        scope.as_deref().unwrap_or("")
+      ),
+    }
+  }
+}
+
+/// Access token response of the token URL
+#[derive(Deserialize)]
+struct TokenResponse {
+  access_token: String,
+  expires_in: Option<u64>,
+}
+
+/// Cached access token with its expiration time
+struct AccessToken {
+  token: String,
+  expires_at: Instant,
+}
+
+/// Access tokens are requested per token URL, client id and scope
+type TokenKey = (String, String, Option<String>);
+
+/// Access token of the key, locked while it's requested, so the concurrent
+/// requests of the key share the new token
+type TokenSlot = Arc<tokio::sync::Mutex<Option<AccessToken>>>;
+
+/// OAuth2 access tokens shared between the resolver clones
+#[derive(Clone, Default)]
+pub struct Authenticator {
+  tokens: Arc<Mutex<HashMap<TokenKey, TokenSlot>>>,
+}
+
+impl fmt::Debug for Authenticator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Authenticator").finish_non_exhaustive()
+  }
+}
+
+impl Authenticator {
+  /// Authenticating the endpoint request, returning it with the OAuth2 access
+  /// token it's authenticated by
+  pub async fn authorize(
+    &self,
+    http: &Client,
+    request: RequestBuilder,
+    auth: &Auth,
+  ) -> anyhow::Result<(RequestBuilder, Option<String>)> {
+    Ok(match auth {
+      Auth::ApiKey {
+        name,
+        value,
+        location: KeyLocation::Header,
+      } => (request.header(name, value), None),
+      Auth::ApiKey {
+        name,
+        value,
+        location: KeyLocation::Query,
+      } => (request.query(&[(name, value)]), None),
+      Auth::Basic { username, password } => {
+        (request.basic_auth(username, Some(password)), None)
+      }
+      Auth::Bearer { token_file } => {
+        let path = token_file.clone();
+        let token =
+          tokio::task::spawn_blocking(move || std::fs::read_to_string(path))
+            .await?
+            .map_err(|err| {
+              anyhow!("bearer token file {} reading error: {}", token_file, err)
+            })?;
+        (request.bearer_auth(token.trim()), None)
+      }
+      Auth::OAuth2 {
+        token_url,
+        client_id,
+        client_secret,
+        scope,
+      } => {
+        let token = self
+          .token(http, token_url, client_id, client_secret, scope.as_deref())
+          .await?;
+        (request.bearer_auth(&token), Some(token))
+      }
+    })
+  }
+
+  /// Forgetting the access token rejected by the endpoint, so the next
+  /// request gets a new one. The token refreshed by a concurrent request
+  /// meanwhile is kept.
+  pub async fn invalidate(&self, auth: &Auth, rejected: &str) {
+    if let Auth::OAuth2 {
+      token_url,
+      client_id,
+      scope,
+      ..
+    } = auth
+    {
+      let slot = self.slot(token_url, client_id, scope.as_deref());
+      let mut token = slot.lock().await;
+      if token.as_ref().is_some_and(|token| token.token == rejected) {
+        *token = None;
+      }
+    }
+  }
+
+  /// Token slot of the token URL, client id and scope
+  fn slot(
+    &self,
+    token_url: &str,
+    client_id: &str,
+    scope: Option<&str>,
+  ) -> TokenSlot {
+    let key = (
+      token_url.to_string(),
+      client_id.to_string(),
+      scope.map(str::to_string),
+    );
+    self.tokens.lock().unwrap().entry(key).or_default().clone()
+  }
+
+  /// Cached access token of the client or the new one when it expires soon
+  async fn token(
+    &self,
+    http: &Client,
+    token_url: &str,
+    client_id: &str,
+    client_secret: &str,
+    scope: Option<&str>,
+  ) -> anyhow::Result<String> {
+    // Only the slot of the key is locked while requesting the token, so the
+    // concurrent requests share the refreshed token without waiting for the
+    // other clients
+    let slot = self.slot(token_url, client_id, scope);
+    let mut cached = slot.lock().await;
+    let valid_until = Instant::now() + EXPIRY_MARGIN;
+    if let Some(token) = cached.as_ref().filter(|t| t.expires_at > valid_until)
+    {
+      return Ok(token.token.clone());
+    }
+
+    trace!("requesting the access token: {}", token_url);
+    let mut form = vec![("grant_type", "client_credentials")];
+    if let Some(scope) = scope {
+      form.push(("scope", scope));
+    }
+    let response = http
+      .post(token_url)
+      .basic_auth(client_id, Some(client_secret))
+      .form(&form)
+      .send()
+      .await?;
+    if !response.status().is_success() {
+      bail!(
+        "token URL {} responded with the status: {}",
+        token_url,
+        response.status()
+      )
+    }
+    let token: TokenResponse =
+      serde_json::from_slice(&response.bytes().await?)?;
+    let lifetime = token
+      .expires_in
+      .map(Duration::from_secs)
+      .unwrap_or(DEFAULT_LIFETIME);
+    *cached = Some(AccessToken {
+      token: token.access_token.clone(),
+      expires_at: Instant::now() + lifetime,
+    });
+    Ok(token.access_token)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use mockito::{Matcher, Server};
+  use reqwest::header::AUTHORIZATION;
+  use std::io::Write;
+
+  /// Request to the endpoint authenticated by the scheme
+  async fn authorized(
+    authenticator: &Authenticator,
+    auth: &Auth,
+  ) -> reqwest::Request {
+    let http = Client::new();
+    let request = http.get("http://localhost/facts");
+    authenticator
+      .authorize(&http, request, auth)
+      .await
+      .unwrap()
+      .0
+      .build()
+      .unwrap()
+  }
+
+  /// API key, basic and bearer token schemes
+  #[tokio::test]
+  async fn static_schemes() {
+    let authenticator = Authenticator::default();
+    let api_key = |location| Auth::ApiKey {
+      name: "x-api-key".to_string(),
+      value: "secret".to_string(),
+      location,
+    };
+
+    let request =
+      authorized(&authenticator, &api_key(KeyLocation::Header)).await;
+    assert_eq!(request.headers()["x-api-key"], "secret");
+    let request =
+      authorized(&authenticator, &api_key(KeyLocation::Query)).await;
+    assert_eq!(request.url().query(), Some("x-api-key=secret"));
+
+    let basic = Auth::Basic {
+      username: "user".to_string(),
+      password: "pass".to_string(),
+    };
+    let request = authorized(&authenticator, &basic).await;
+    assert_eq!(request.headers()[AUTHORIZATION], "Basic dXNlcjpwYXNz");
+
+    let mut token_file = tempfile::NamedTempFile::new().unwrap();
+    token_file.write_all(b"file-token\n").unwrap();
+    let bearer = Auth::Bearer {
+      token_file: token_file.path().to_str().unwrap().to_string(),
+    };
+    let request = authorized(&authenticator, &bearer).await;
+    assert_eq!(request.headers()[AUTHORIZATION], "Bearer file-token");
+  }
+
+  /// OAuth2 access tokens are cached until they expire or get invalidated
+  #[tokio::test]
+  async fn oauth2_token_caching() {
+    for (expires_in, token_requests) in [(3600, 2), (1, 4)] {
+      let mut server = Server::new_async().await;
+      let token_mock = server
+        .mock("POST", "/token")
+        .match_header(AUTHORIZATION.as_str(), "Basic Y2xpZW50OnNlY3JldA==")
+        .match_body(Matcher::UrlEncoded(
+          "grant_type".into(),
+          "client_credentials".into(),
+        ))
+        .with_body(format!(
+          r#"{{"access_token": "oauth-token", "expires_in": {}}}"#,
+          expires_in
+        ))
+        .expect(token_requests)
+        .create_async()
+        .await;
+      let auth = Auth::OAuth2 {
+        token_url: server.url() + "/token",
+        client_id: "client".to_string(),
+        client_secret: "secret".to_string(),
+        scope: None,
+      };
+
+      let authenticator = Authenticator::default();
+      for _n in 0..2 {
+        let request = authorized(&authenticator, &auth).await;
+        assert_eq!(request.headers()[AUTHORIZATION], "Bearer oauth-token");
+      }
+      // A token rejected earlier does not invalidate the current one
+      authenticator.invalidate(&auth, "revoked-token").await;
+      authorized(&authenticator, &auth).await;
+      authenticator.invalidate(&auth, "oauth-token").await;
+      authorized(&authenticator, &auth).await;
+      token_mock.assert_async().await;
+    }
+  }
+
+  /// Access tokens of the same client are cached per scope
+  #[tokio::test]
+  async fn oauth2_token_scopes() {
+    let mut server = Server::new_async().await;
+    let mut mocks = Vec::new();
+    for scope in ["dogs", "cats"] {
+      let mock = server
+        .mock("POST", "/token")
+        .match_body(Matcher::UrlEncoded("scope".into(), scope.into()))
+        .with_body(format!(r#"{{"access_token": "{}-token"}}"#, scope))
+        .expect(1)
+        .create_async()
+        .await;
+      mocks.push(mock);
+    }
+    let auth = |scope: &str| Auth::OAuth2 {
+      token_url: server.url() + "/token",
+      client_id: "client".to_string(),
+      client_secret: "secret".to_string(),
+      scope: Some(scope.to_string()),
+    };
+
+    let authenticator = Authenticator::default();
+    for scope in ["dogs", "cats", "dogs", "cats"] {
+      let request = authorized(&authenticator, &auth(scope)).await;
+      assert_eq!(
+        request.headers()[AUTHORIZATION],
+        format!("Bearer {}-token", scope).as_str()
+      );
+    }
+    for mock in mocks {
+      mock.assert_async().await;
+    }
+  }
+
+  /// The token URL error fails the request
+  #[tokio::test]
+  async fn oauth2_token_error() {
+    let mut server = Server::new_async().await;
+    server
+      .mock("POST", "/token")
+      .with_status(401)
+      .create_async()
+      .await;
+    let auth = Auth::OAuth2 {
+      token_url: server.url() + "/token",
+      client_id: "client".to_string(),
+      client_secret: "wrong".to_string(),
+      scope: Some("facts".to_string()),
+    };
+
+    let http = Client::new();
+    let request = http.get("http://localhost/facts");
+    let result = Authenticator::default()
+      .authorize(&http, request, &auth)
+      .await;
+    assert!(result.is_err());
+  }
+}
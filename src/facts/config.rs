@@ -1,9 +1,517 @@
-use serde::{Deserialize, Serialize};
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 
 /// Facts configuration structure
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
 pub struct FactsConfig {
   pub default: String,
-  pub facts: HashMap<String, String>,
+  /// Animal fact sources, a single endpoint URL or a list of sources
+  pub facts: HashMap<String, AnimalConfig>,
+  /// Local persistent store of the fetched facts, disabled when omitted
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub store: Option<StoreConfig>,
+  /// Serving recently resolved facts when the endpoint request fails
+  #[serde(default)]
+  pub stale: StaleConfig,
+  /// Picking the animal when the default is `any`
+  #[serde(default)]
+  pub any: AnyConfig,
+  /// Avoiding the facts the same client has seen recently
+  #[serde(default)]
+  pub dedup: DedupConfig,
+  /// Sharing one in-flight endpoint request between the concurrent callers,
+  /// so they get the same fact of the endpoints returning a random one per call
+  #[serde(default)]
+  pub coalesce: bool,
+  /// Hedging the slow endpoint requests, disabled when omitted
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hedge: Option<HedgeConfig>,
+  /// Keeping the extra facts of the endpoints returning several facts
+  #[serde(default)]
+  pub prefetch: PrefetchConfig,
+  /// Language requested from the endpoints by the `{{language}}` template
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub language: Option<String>,
+  /// Quality rules of the facts returned by the adapters per animal
+  #[serde(default)]
+  pub filters: HashMap<String, FilterConfig>,
+}
+
+/// Fact sources of an animal
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum AnimalConfig {
+  /// Single endpoint URL transformed by the adapter named after the animal
+  Endpoint(String),
+  /// Multiple sources selected by the strategy
+  Sources(SourcesConfig),
+}
+
+impl AnimalConfig {
+  /// Sources of the animal, the single endpoint being the only source
+  pub fn to_sources(&self) -> SourcesConfig {
+    match self {
+      AnimalConfig::Endpoint(url) => SourcesConfig {
+        strategy: Strategy::default(),
+        sources: vec![SourceConfig {
+          url: url.clone(),
+          ..Default::default()
+        }],
+      },
+      AnimalConfig::Sources(sources) => sources.clone(),
+    }
+  }
+}
+
+impl From<String> for AnimalConfig {
+  fn from(url: String) -> Self {
+    AnimalConfig::Endpoint(url)
+  }
+}
+
+impl From<&str> for AnimalConfig {
+  fn from(url: &str) -> Self {
+    AnimalConfig::Endpoint(url.to_string())
+  }
+}
+
+/// Multiple fact sources of an animal
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SourcesConfig {
+  /// Source selection strategy, `failover` by default
+  #[serde(default)]
+  pub strategy: Strategy,
+  pub sources: Vec<SourceConfig>,
+}
+
+/// Fact source endpoint with its adapter
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SourceConfig {
+  /// Endpoint URL or the `file://` corpus path
+  pub url: String,
+  /// Adapter transforming the endpoint response, the animal name by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub adapter: Option<AdapterConfig>,
+  /// Relative weight of the source for the `weighted_random` strategy
+  #[serde(default = "default_weight")]
+  pub weight: u32,
+  /// Overriding the `coalesce` flag of the facts configuration for the source
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub coalesce: Option<bool>,
+  /// HTTP method of the endpoint request, `GET` by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub method: Option<String>,
+  /// Header templates of the endpoint request
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub headers: HashMap<String, String>,
+  /// Query parameter templates of the endpoint request
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub query: HashMap<String, String>,
+  /// Body template of the endpoint request
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub body: Option<String>,
+  /// Authentication of the endpoint request
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub auth: Option<AuthConfig>,
+  /// Path of the JSON Schema file the successful endpoint responses are
+  /// validated against before the adapter
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub schema: Option<String>,
+}
+
+/// Endpoint authentication scheme. The secrets are templates, so they can be
+/// read from the environment variables.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthConfig {
+  /// Static API key sent in the header or the query parameter
+  ApiKey {
+    name: String,
+    value: String,
+    #[serde(default)]
+    location: KeyLocation,
+  },
+  /// HTTP basic authentication
+  Basic { username: String, password: String },
+  /// Bearer token read from the file on every request, so the rotated tokens
+  /// are picked up
+  Bearer { token_file: String },
+  /// OAuth2 client credentials grant with the access tokens cached until
+  /// they expire
+  #[serde(rename = "oauth2")]
+  OAuth2 {
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+  },
+}
+
+/// Where the API key is sent
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyLocation {
+  #[default]
+  Header,
+  Query,
+}
+
+impl Default for SourceConfig {
+  fn default() -> Self {
+    SourceConfig {
+      url: String::new(),
+      adapter: None,
+      weight: default_weight(),
+      coalesce: None,
+      method: None,
+      headers: HashMap::new(),
+      query: HashMap::new(),
+      body: None,
+      auth: None,
+      schema: None,
+    }
+  }
+}
+
+fn default_weight() -> u32 {
+  1
+}
+
+/// Adapter of the source
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum AdapterConfig {
+  /// Adapter of the animal endpoint by its name like `dog`
+  Named(String),
+  /// Generic adapter extracting the facts from the response format
+  Extract(ExtractConfig),
+}
+
+impl From<&str> for AdapterConfig {
+  fn from(name: &str) -> Self {
+    AdapterConfig::Named(name.to_string())
+  }
+}
+
+/// Generic adapter by the response format
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExtractConfig {
+  Text(TextConfig),
+  Xml(XmlConfig),
+  Csv(CsvConfig),
+  Html(HtmlConfig),
+  Script(ScriptConfig),
+  Wasm(WasmConfig),
+}
+
+/// Plain text adapter configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TextConfig {
+  /// Trimming the whitespace around the facts
+  pub trim: bool,
+  /// Every non-empty line of the response is a fact
+  pub lines: bool,
+  /// Index of the line being the fact, starting from `0`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub line: Option<usize>,
+}
+
+impl Default for TextConfig {
+  fn default() -> Self {
+    TextConfig {
+      trim: true,
+      lines: false,
+      line: None,
+    }
+  }
+}
+
+/// XML adapter configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct XmlConfig {
+  /// XPath expression of the facts, like `//item/description`
+  pub xpath: String,
+}
+
+/// CSV adapter configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CsvConfig {
+  /// Column of the facts, the header name or the index starting from `0`
+  pub column: CsvColumn,
+  /// Field delimiter
+  #[serde(default = "default_delimiter")]
+  pub delimiter: char,
+  /// The first row is the header
+  #[serde(default = "default_has_headers")]
+  pub has_headers: bool,
+}
+
+fn default_delimiter() -> char {
+  ','
+}
+
+fn default_has_headers() -> bool {
+  true
+}
+
+/// CSV column by the header name or the index
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CsvColumn {
+  Index(usize),
+  Name(String),
+}
+
+/// HTML adapter configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HtmlConfig {
+  /// CSS selector of the fact elements, like `ul.facts > li`
+  pub selector: String,
+}
+
+/// Order in which the sources of an animal are tried. Whatever the strategy,
+/// the rest of the sources are tried in turn when the selected one fails.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+  /// Sources in the configured order
+  #[default]
+  Failover,
+  /// Starting from the next source on every request
+  RoundRobin,
+  /// Random order by the source weights
+  WeightedRandom,
+  /// Sources with the lowest observed latency first
+  Fastest,
+}
+
+/// Random animal selection of the `any` default
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AnyConfig {
+  /// Relative weights of the animals, `1` for the animals not listed
+  pub weights: HashMap<String, u32>,
+  /// Animals never picked for `any`
+  pub exclude: Vec<String>,
+  /// Maximum number of times the same animal is picked in a row, `0` for no
+  /// limit
+  pub max_repeats: usize,
+  /// Random generator seed making the picks deterministic
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub seed: Option<u64>,
+}
+
+/// Per-client duplicate facts suppression configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct DedupConfig {
+  /// Number of the last facts remembered per client, `0` (the default)
+  /// disables the suppression
+  pub history: usize,
+  /// Maximum number of the re-fetches when the fact is a repeat
+  pub max_refetches: usize,
+  /// Maximum number of the remembered clients, the oldest ones are forgotten
+  pub max_clients: usize,
+}
+
+impl Default for DedupConfig {
+  fn default() -> Self {
+    DedupConfig {
+      history: 0,
+      max_refetches: 3,
+      max_clients: 10000,
+    }
+  }
+}
+
+/// Hedged endpoint requests configuration. The hedged request is sent when
+/// the first one has not completed after the delay.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct HedgeConfig {
+  /// Fixed delay in milliseconds, used until there are enough observed
+  /// latencies when the percentile is configured too
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub delay: Option<u64>,
+  /// Percentile of the observed latencies of the animal used as the delay,
+  /// like `95.0`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub percentile: Option<f64>,
+  /// Sending the hedged request to the next source of the animal instead of
+  /// the same one
+  pub alternate: bool,
+}
+
+/// Local SQLite fact store configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StoreConfig {
+  /// SQLite database file path, created when it does not exist
+  pub path: String,
+  /// Serving a stored fact when the animal endpoint request fails
+  #[serde(default = "default_serve_on_failure")]
+  pub serve_on_failure: bool,
+}
+
+fn default_serve_on_failure() -> bool {
+  true
+}
+
+/// Stale facts fallback configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StaleConfig {
+  /// Maximum age of the served stale fact in seconds, `0` disables the fallback
+  pub max_staleness: u64,
+  /// Number of the recent facts remembered per animal
+  pub capacity: usize,
+}
+
+impl Default for StaleConfig {
+  fn default() -> Self {
+    StaleConfig {
+      max_staleness: 300,
+      capacity: 16,
+    }
+  }
+}
+
+/// Prefetched facts configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PrefetchConfig {
+  /// Number of the extra facts kept per animal, `0` drops them
+  pub capacity: usize,
+  /// Maximum age of the served extra fact in seconds
+  pub max_age: u64,
+  /// Number of the facts requested from the endpoints by the `{{count}}`
+  /// template
+  pub batch: usize,
+}
+
+impl Default for PrefetchConfig {
+  fn default() -> Self {
+    PrefetchConfig {
+      capacity: 32,
+      max_age: 300,
+      batch: 1,
+    }
+  }
+}
+
+/// Scripting adapter configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ScriptConfig {
+  /// Path of the Rhai script file
+  pub path: String,
+  /// Maximum number of the operations the script runs
+  #[serde(default = "default_max_operations")]
+  pub max_operations: u64,
+  /// Maximum length of the strings the script creates in bytes
+  #[serde(default = "default_max_string_size")]
+  pub max_string_size: usize,
+  /// Maximum number of the elements of the arrays and maps the script creates
+  #[serde(default = "default_max_collection_size")]
+  pub max_collection_size: usize,
+}
+
+fn default_max_operations() -> u64 {
+  100_000
+}
+
+fn default_max_string_size() -> usize {
+  1024 * 1024
+}
+
+fn default_max_collection_size() -> usize {
+  10_000
+}
+
+/// WebAssembly plugin adapter configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WasmConfig {
+  /// Path of the WebAssembly module file in the binary or text format
+  pub path: String,
+  /// Fuel of a single transform, roughly the number of the executed
+  /// instructions
+  #[serde(default = "default_fuel")]
+  pub fuel: u64,
+  /// Maximum linear memory of the plugin in bytes
+  #[serde(default = "default_max_memory")]
+  pub max_memory: usize,
+}
+
+fn default_fuel() -> u64 {
+  10_000_000
+}
+
+fn default_max_memory() -> usize {
+  16 * 1024 * 1024
+}
+
+/// Fact quality rules of an animal
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct FilterConfig {
+  /// Minimum length of the fact text in characters
+  pub min_length: usize,
+  /// Maximum length of the fact text in characters, unlimited when omitted
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_length: Option<usize>,
+  /// Decoding the HTML entities and collapsing the whitespace of the fact text
+  /// before checking the rules
+  pub normalize: bool,
+  /// Words rejecting the facts containing them, case-insensitive
+  pub banned_words: Vec<String>,
+  /// Regular expressions rejecting the facts matching them
+  pub deny: Vec<Pattern>,
+  /// ISO 639-3 codes of the allowed languages detected from the fact text, any
+  /// language when empty
+  pub languages: Vec<String>,
+  /// Number of the repeated fetches when all the facts of the response are
+  /// rejected
+  pub max_refetches: usize,
+}
+
+impl Default for FilterConfig {
+  fn default() -> Self {
+    FilterConfig {
+      min_length: 1,
+      max_length: None,
+      normalize: true,
+      banned_words: Vec::new(),
+      deny: Vec::new(),
+      languages: Vec::new(),
+      max_refetches: 3,
+    }
+  }
+}
+
+/// Regular expression compiled when the configuration is loaded, so the
+/// invalid ones fail the configuration
+#[derive(Debug, Clone)]
+pub struct Pattern(pub Regex);
+
+impl PartialEq for Pattern {
+  fn eq(&self, other: &Self) -> bool {
+    self.0.as_str() == other.0.as_str()
+  }
+}
+
+impl Serialize for Pattern {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.0.as_str())
+  }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+  fn deserialize<D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map(Pattern).map_err(de::Error::custom)
+  }
 }
@@ -0,0 +1,182 @@
+//! Local file fact corpus
+//!
+//! Serving facts from local files instead of the HTTP endpoints for the animals
+//! configured with the `file://` URL. Supported formats are JSON lines (`.jsonl`
+//! or `.ndjson`), where every line is a JSON string or an object with the
+//! `fact` or `text` field, and CSV (`.csv`) with the `fact` or `text` column or
+//! the first column when there is no such header.
+//!
+//! Files are loaded on the first request and reloaded once their modification
+//! time or size changes, so the corpus can be edited without a restart.
+
+use anyhow::{anyhow, bail};
+use rand::seq::SliceRandom;
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::SystemTime,
+};
+use tracing::{instrument, trace};
+
+/// URL scheme of the animals served from the local files
+pub const FILE_SCHEME: &str = "file://";
+
+/// Facts of a loaded file with the metadata used to detect changes
+#[derive(Debug)]
+struct LoadedFile {
+  modified: SystemTime,
+  len: u64,
+  facts: Vec<String>,
+}
+
+/// Cache of the loaded corpus files shared between the resolver clones
+#[derive(Debug, Clone, Default)]
+pub struct FileCorpus {
+  files: Arc<Mutex<HashMap<PathBuf, LoadedFile>>>,
+}
+
+impl FileCorpus {
+  /// Random fact from the file, reloading the file when it has changed
+  #[instrument(skip(self))]
+  pub fn random_fact(&self, path: &Path) -> anyhow::Result<String> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?;
+    let len = metadata.len();
+
+    let mut files = self.files.lock().unwrap();
+    let loaded = files.get(path);
+    if loaded.map_or(true, |f| f.modified != modified || f.len != len) {
+      trace!("loading the corpus file");
+      let facts = load_facts(path)?;
+      files.insert(
+        path.to_path_buf(),
+        LoadedFile {
+          modified,
+          len,
+          facts,
+        },
+      );
+    }
+
+    files[path]
+      .facts
+      .choose(&mut rand::thread_rng())
+      .cloned()
+      .ok_or_else(|| anyhow!("corpus file has no facts: {}", path.display()))
+  }
+}
+
+/// Reading all the facts of the file depending on its extension
+fn load_facts(path: &Path) -> anyhow::Result<Vec<String>> {
+  let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+  match extension {
+    "jsonl" | "ndjson" => load_json_lines(&fs::read_to_string(path)?),
+    "csv" => load_csv(&fs::read_to_string(path)?),
+    _ => bail!("unsupported corpus file format: {}", path.display()),
+  }
+}
+
+/// Parsing JSON lines, skipping the empty ones
+fn load_json_lines(content: &str) -> anyhow::Result<Vec<String>> {
+  content
+    .lines()
+    .filter(|line| !line.trim().is_empty())
+    .map(|line| {
+      let value: serde_json::Value = serde_json::from_str(line)?;
+      let fact = match &value {
+        serde_json::Value::String(fact) => Some(fact),
+        serde_json::Value::Object(object) => object
+          .get("fact")
+          .or_else(|| object.get("text"))
+          .and_then(|fact| match fact {
+            serde_json::Value::String(fact) => Some(fact),
+            _ => None,
+          }),
+        _ => None,
+      };
+      fact
+        .cloned()
+        .ok_or_else(|| anyhow!("no fact text in the corpus line: {}", line))
+    })
+    .collect()
+}
+
+/// Parsing CSV with the header, using the `fact` or `text` column or the first
+/// one when there is no such column
+fn load_csv(content: &str) -> anyhow::Result<Vec<String>> {
+  let mut reader = csv::Reader::from_reader(content.as_bytes());
+  let column = reader
+    .headers()?
+    .iter()
+    .position(|header| header == "fact" || header == "text")
+    .unwrap_or(0);
+
+  let mut facts = Vec::new();
+  for record in reader.records() {
+    if let Some(fact) = record?.get(column) {
+      if !fact.trim().is_empty() {
+        facts.push(fact.to_string());
+      }
+    }
+  }
+  Ok(facts)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+  use std::time::Duration;
+  use tempfile::Builder;
+
+  /// Both plain strings and objects are supported in JSON lines
+  #[test]
+  fn json_lines() {
+    let facts = load_json_lines(
+      "\"first fact\"\n\n{\"fact\":\"second fact\"}\n{\"text\":\"third fact\"}",
+    )
+    .unwrap();
+
+    assert_eq!(facts, vec!["first fact", "second fact", "third fact"]);
+    assert!(load_json_lines("{\"id\":1}").is_err());
+  }
+
+  /// The `fact` column is used when present, the first one otherwise
+  #[test]
+  fn csv_columns() {
+    let facts = load_csv("id,fact\n1,first fact\n2,\"second, fact\"").unwrap();
+    assert_eq!(facts, vec!["first fact", "second, fact"]);
+
+    let facts = load_csv("sentence\nonly fact").unwrap();
+    assert_eq!(facts, vec!["only fact"]);
+  }
+
+  /// Changed files should be reloaded on the next request
+  #[test]
+  fn hot_reload() {
+    let mut file = Builder::new().suffix(".jsonl").tempfile().unwrap();
+    writeln!(file, "\"old fact\"").unwrap();
+    let corpus = FileCorpus::default();
+    assert_eq!(corpus.random_fact(file.path()).unwrap(), "old fact");
+
+    let mut replaced = fs::File::create(file.path()).unwrap();
+    writeln!(replaced, "\"new fact\"").unwrap();
+    replaced
+      .set_modified(SystemTime::now() + Duration::from_secs(10))
+      .unwrap();
+
+    assert_eq!(corpus.random_fact(file.path()).unwrap(), "new fact");
+  }
+
+  /// Unsupported and empty files are errors
+  #[test]
+  fn invalid_files() {
+    let file = Builder::new().suffix(".txt").tempfile().unwrap();
+    assert!(FileCorpus::default().random_fact(file.path()).is_err());
+
+    let file = Builder::new().suffix(".csv").tempfile().unwrap();
+    assert!(FileCorpus::default().random_fact(file.path()).is_err());
+  }
+}
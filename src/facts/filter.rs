@@ -0,0 +1,192 @@
+//! Fact filters
+//!
+//! Quality rules applied to the facts returned by the adapters. The fact text is
+//! normalized first, decoding the HTML entities and collapsing the whitespace,
+//! then checked by the length, banned words, deny patterns and the detected
+//! language. When every fact of the response is rejected, the resolver fetches
+//! the animal again up to the configured limit.
+
+use super::config::FilterConfig;
+use super::Fact;
+use std::fmt;
+use tracing::debug;
+
+/// Reason of the fact rejected by the filter
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+  TooShort(usize),
+  TooLong(usize),
+  BannedWord(String),
+  Denied(String),
+  Language(String),
+}
+
+impl fmt::Display for Rejection {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Rejection::TooShort(length) => write!(f, "too short ({} chars)", length),
+      Rejection::TooLong(length) => write!(f, "too long ({} chars)", length),
+      Rejection::BannedWord(word) => write!(f, "banned word: {}", word),
+      Rejection::Denied(pattern) => write!(f, "denied by: {}", pattern),
+      Rejection::Language(code) => write!(f, "language not allowed: {}", code),
+    }
+  }
+}
+
+/// Error of the endpoint response with every fact rejected by the filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct FactsRejected(pub Vec<Rejection>);
+
+impl fmt::Display for FactsRejected {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let reasons: Vec<String> =
+      self.0.iter().map(Rejection::to_string).collect();
+    write!(f, "all the facts were rejected: {}", reasons.join("; "))
+  }
+}
+
+impl std::error::Error for FactsRejected {}
+
+/// Facts of the animal passing the filter, the `FactsRejected` error when every
+/// fact is rejected
+pub fn filter_facts(
+  config: &FilterConfig,
+  animal: &str,
+  facts: Vec<Fact>,
+) -> anyhow::Result<Vec<Fact>> {
+  let mut rejections = Vec::new();
+  let passed: Vec<Fact> = facts
+    .into_iter()
+    .filter_map(|fact| match apply(config, fact) {
+      Ok(fact) => Some(fact),
+      Err(rejection) => {
+        debug!(animal, "fact rejected: {}", rejection);
+        rejections.push(rejection);
+        None
+      }
+    })
+    .collect();
+  if passed.is_empty() && !rejections.is_empty() {
+    return Err(FactsRejected(rejections).into());
+  }
+  Ok(passed)
+}
+
+/// Normalized fact or the reason it's rejected for
+pub fn apply(config: &FilterConfig, mut fact: Fact) -> Result<Fact, Rejection> {
+  if config.normalize {
+    fact.text = normalize(&fact.text);
+  }
+
+  let length = fact.text.chars().count();
+  if length < config.min_length {
+    return Err(Rejection::TooShort(length));
+  }
+  if config
+    .max_length
+    .is_some_and(|max_length| length > max_length)
+  {
+    return Err(Rejection::TooLong(length));
+  }
+
+  let lowercase = fact.text.to_lowercase();
+  let words: Vec<&str> = lowercase
+    .split(|c: char| !c.is_alphanumeric() && c != '\'')
+    .collect();
+  if let Some(word) = config
+    .banned_words
+    .iter()
+    .find(|banned| words.contains(&banned.to_lowercase().as_str()))
+  {
+    return Err(Rejection::BannedWord(word.clone()));
+  }
+
+  if let Some(pattern) = config.deny.iter().find(|p| p.0.is_match(&fact.text)) {
+    return Err(Rejection::Denied(pattern.0.to_string()));
+  }
+
+  if !config.languages.is_empty() {
+    // Short texts are often detected unreliably, so only the reliably detected
+    // languages reject the fact
+    if let Some(info) = whatlang::detect(&fact.text) {
+      let code = info.lang().code();
+      if info.is_reliable() && !config.languages.iter().any(|l| l == code) {
+        return Err(Rejection::Language(code.to_string()));
+      }
+    }
+  }
+  Ok(fact)
+}
+
+/// Text with the HTML entities decoded and the whitespace runs collapsed into
+/// single spaces
+fn normalize(text: &str) -> String {
+  let decoded = html_escape::decode_html_entities(text);
+  decoded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::facts::config::Pattern;
+  use regex::Regex;
+
+  /// Text is normalized and checked by the length
+  #[test]
+  fn normalizing_and_length() {
+    let config = FilterConfig {
+      max_length: Some(20),
+      ..Default::default()
+    };
+
+    let fact =
+      apply(&config, Fact::new("  Dogs &amp; cats\n\tsleep ")).unwrap();
+    assert_eq!(fact.text, "Dogs & cats sleep");
+    assert_eq!(
+      apply(&config, Fact::new(" \n ")),
+      Err(Rejection::TooShort(0))
+    );
+    assert_eq!(
+      apply(&config, Fact::new("a".repeat(21))),
+      Err(Rejection::TooLong(21))
+    );
+  }
+
+  /// Banned words, deny patterns and languages reject the facts
+  #[test]
+  fn content_rules() {
+    let config = FilterConfig {
+      banned_words: vec!["Cruel".to_string()],
+      deny: vec![Pattern(Regex::new(r"https?://").unwrap())],
+      languages: vec!["eng".to_string()],
+      ..Default::default()
+    };
+
+    let english = "Dogs have an extraordinary sense of smell that helps them \
+      find food and friends.";
+    assert!(apply(&config, Fact::new(english)).is_ok());
+    assert!(apply(&config, Fact::new("Cruelty is not banned")).is_ok());
+    assert_eq!(
+      apply(&config, Fact::new("Some cruel dog fact")),
+      Err(Rejection::BannedWord("Cruel".to_string()))
+    );
+    assert!(matches!(
+      apply(&config, Fact::new("Read more at https://some-dog-facts")),
+      Err(Rejection::Denied(_))
+    ));
+    let german = "Hunde haben einen außergewöhnlichen Geruchssinn, der ihnen \
+      hilft, Futter und Freunde zu finden.";
+    assert_eq!(
+      apply(&config, Fact::new(german)),
+      Err(Rejection::Language("deu".to_string()))
+    );
+
+    let err =
+      filter_facts(&config, "dog", vec![Fact::new(german)]).unwrap_err();
+    assert!(err.downcast_ref::<FactsRejected>().is_some());
+    let facts =
+      filter_facts(&config, "dog", vec![Fact::new(german), Fact::new(english)])
+        .unwrap();
+    assert_eq!(facts.len(), 1);
+  }
+}
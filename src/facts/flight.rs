@@ -0,0 +1,150 @@
+//! Single-flight endpoint requests
+//!
+//! Concurrent callers requesting the same endpoint share one in-flight request
+//! and its response instead of requesting the endpoint each. The flight is
+//! forgotten once it completes, so the next caller starts a new request.
+
+use anyhow::anyhow;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::{
+  collections::HashMap,
+  future::Future,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+};
+use tracing::trace;
+
+/// Shared response of the in-flight request. Errors are shared by the
+/// reference because they can not be cloned.
+type Flight<T> = Shared<BoxFuture<'static, Result<T, Arc<anyhow::Error>>>>;
+
+/// In-flight requests by the key with the flight id
+type Flights<T> = HashMap<String, (u64, Flight<T>)>;
+
+/// In-flight requests shared between the resolver clones
+#[derive(Debug)]
+pub struct SingleFlight<T: Clone> {
+  flights: Arc<Mutex<Flights<T>>>,
+  next_id: Arc<AtomicU64>,
+}
+
+impl<T: Clone> Clone for SingleFlight<T> {
+  fn clone(&self) -> Self {
+    SingleFlight {
+      flights: self.flights.clone(),
+      next_id: self.next_id.clone(),
+    }
+  }
+}
+
+impl<T: Clone> Default for SingleFlight<T> {
+  fn default() -> Self {
+    SingleFlight {
+      flights: Arc::default(),
+      next_id: Arc::default(),
+    }
+  }
+}
+
+impl<T: Clone + Send + Sync + 'static> SingleFlight<T> {
+  /// Joining the in-flight request of the key or starting the new one
+  pub async fn run(
+    &self,
+    key: &str,
+    request: impl Future<Output = anyhow::Result<T>> + Send + 'static,
+  ) -> anyhow::Result<T> {
+    let (id, flight) = {
+      let mut flights = self.flights.lock().unwrap();
+      match flights.get(key) {
+        Some((id, flight)) => {
+          trace!("joining the in-flight request: {}", key);
+          (*id, flight.clone())
+        }
+        None => {
+          let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+          let flight = request.map(|result| result.map_err(Arc::new));
+          let flight = flight.boxed().shared();
+          flights.insert(key.to_string(), (id, flight.clone()));
+          (id, flight)
+        }
+      }
+    };
+
+    let result = flight.await;
+    // Any of the callers finishing first forgets the flight, so it is
+    // forgotten even when the caller started it is cancelled
+    let mut flights = self.flights.lock().unwrap();
+    if flights
+      .get(key)
+      .is_some_and(|(flight_id, _)| *flight_id == id)
+    {
+      flights.remove(key);
+    }
+    result.map_err(|err| anyhow!("{:#}", err))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::AtomicUsize;
+  use std::time::Duration;
+
+  /// Counting request finishing after a delay
+  fn request(
+    calls: &Arc<AtomicUsize>,
+    result: anyhow::Result<String>,
+  ) -> impl Future<Output = anyhow::Result<String>> + Send + 'static {
+    let calls = calls.clone();
+    async move {
+      calls.fetch_add(1, Ordering::SeqCst);
+      tokio::time::sleep(Duration::from_millis(50)).await;
+      result
+    }
+  }
+
+  /// Concurrent callers share the request, the next ones start a new one
+  #[tokio::test]
+  async fn concurrent_callers_share_request() {
+    let single_flight = SingleFlight::<String>::default();
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let results = futures::future::join_all((0..5).map(|_| {
+      single_flight.run("dog", request(&calls, Ok("dog fact".to_string())))
+    }))
+    .await;
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    for result in results {
+      assert_eq!(result.unwrap(), "dog fact");
+    }
+
+    let result = single_flight
+      .run("dog", request(&calls, Err(anyhow!("endpoint is down"))))
+      .await;
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(result.unwrap_err().to_string(), "endpoint is down");
+  }
+
+  /// The flight is forgotten when the caller started it is cancelled
+  #[tokio::test]
+  async fn cancelled_leader() {
+    let single_flight = SingleFlight::<String>::default();
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let leader =
+      single_flight.run("cat", request(&calls, Ok("cat fact".to_string())));
+    assert!(tokio::time::timeout(Duration::from_millis(10), leader)
+      .await
+      .is_err());
+    let follower =
+      single_flight.run("cat", request(&calls, Ok("other fact".to_string())));
+    assert_eq!(follower.await.unwrap(), "cat fact");
+
+    let next =
+      single_flight.run("cat", request(&calls, Ok("next fact".to_string())));
+    assert_eq!(next.await.unwrap(), "next fact");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+  }
+}
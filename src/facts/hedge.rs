@@ -0,0 +1,146 @@
+//! Hedged endpoint requests
+//!
+//! When the endpoint request of an animal has not completed after the hedging
+//! delay, the resolver sends the second request and uses the one finishing
+//! first. The delay is fixed or the percentile of the latencies observed for
+//! the animal. The numbers of the hedged requests and of the hedged requests
+//! that won are exported in the tracing events.
+
+use super::config::HedgeConfig;
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  time::Duration,
+};
+use tracing::info;
+
+/// Number of the latest latencies per animal the percentile is computed from
+const LATENCY_WINDOW: usize = 100;
+
+/// Minimum number of the observed latencies to use the percentile
+const MIN_SAMPLES: usize = 10;
+
+/// Hedging policy with the observed latencies and the hedge counts shared
+/// between the resolver clones
+#[derive(Debug, Clone)]
+pub struct Hedging {
+  config: HedgeConfig,
+  latencies: Arc<Mutex<HashMap<String, VecDeque<Duration>>>>,
+  hedges: Arc<AtomicU64>,
+  wins: Arc<AtomicU64>,
+}
+
+impl Hedging {
+  pub fn new(config: HedgeConfig) -> Self {
+    Hedging {
+      config,
+      latencies: Arc::default(),
+      hedges: Arc::default(),
+      wins: Arc::default(),
+    }
+  }
+
+  /// Hedged requests are sent to the next source of the animal
+  pub fn alternate(&self) -> bool {
+    self.config.alternate
+  }
+
+  /// Delay before the hedged request of the animal, `None` when the request
+  /// should not be hedged
+  pub fn delay(&self, animal: &str) -> Option<Duration> {
+    let observed = self.config.percentile.and_then(|percentile| {
+      let latencies = self.latencies.lock().unwrap();
+      let mut latencies: Vec<Duration> =
+        latencies.get(animal)?.iter().copied().collect();
+      if latencies.len() < MIN_SAMPLES {
+        return None;
+      }
+      latencies.sort();
+      let rank = (percentile.clamp(0.0, 100.0) / 100.0 * latencies.len() as f64)
+        .ceil() as usize;
+      Some(latencies[rank.clamp(1, latencies.len()) - 1])
+    });
+    observed.or(self.config.delay.map(Duration::from_millis))
+  }
+
+  /// Recording the latency of the successful request of the animal
+  pub fn record_latency(&self, animal: &str, latency: Duration) {
+    let mut latencies = self.latencies.lock().unwrap();
+    let latencies = latencies.entry(animal.to_string()).or_default();
+    if latencies.len() >= LATENCY_WINDOW {
+      latencies.pop_front();
+    }
+    latencies.push_back(latency);
+  }
+
+  /// Counting the hedged request sent for the animal
+  pub fn record_hedge(&self, animal: &str, delay: Duration) {
+    let hedges = self.hedges.fetch_add(1, Ordering::Relaxed) + 1;
+    let wins = self.wins.load(Ordering::Relaxed);
+    info!(
+      animal,
+      delay_ms = delay.as_millis() as u64,
+      hedges,
+      wins,
+      "hedging the slow endpoint request"
+    );
+  }
+
+  /// Counting the hedged request of the animal finishing first
+  pub fn record_win(&self, animal: &str) {
+    let wins = self.wins.fetch_add(1, Ordering::Relaxed) + 1;
+    let hedges = self.hedges.load(Ordering::Relaxed);
+    info!(animal, hedges, wins, "hedged request finished first");
+  }
+
+  /// Numbers of the hedged requests and of the ones finishing first
+  #[cfg(test)]
+  pub fn counts(&self) -> (u64, u64) {
+    (
+      self.hedges.load(Ordering::Relaxed),
+      self.wins.load(Ordering::Relaxed),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The fixed delay is used until there are enough observed latencies
+  #[test]
+  fn percentile_delay() {
+    let hedging = Hedging::new(HedgeConfig {
+      delay: Some(250),
+      percentile: Some(90.0),
+      ..Default::default()
+    });
+    assert_eq!(hedging.delay("dog"), Some(Duration::from_millis(250)));
+
+    for millis in 1..=20 {
+      hedging.record_latency("dog", Duration::from_millis(millis * 10));
+    }
+    assert_eq!(hedging.delay("dog"), Some(Duration::from_millis(180)));
+    assert_eq!(hedging.delay("cat"), Some(Duration::from_millis(250)));
+
+    let disabled = Hedging::new(HedgeConfig::default());
+    assert_eq!(disabled.delay("dog"), None);
+  }
+
+  /// Only the latest latencies are taken into account
+  #[test]
+  fn latency_window() {
+    let hedging = Hedging::new(HedgeConfig {
+      percentile: Some(100.0),
+      ..Default::default()
+    });
+    hedging.record_latency("dog", Duration::from_secs(10));
+    for _n in 0..LATENCY_WINDOW {
+      hedging.record_latency("dog", Duration::from_millis(100));
+    }
+    assert_eq!(hedging.delay("dog"), Some(Duration::from_millis(100)));
+  }
+}
@@ -7,22 +7,198 @@
 //! This module is tested at the upper level in the `handlers.rs` to leverage the
 //! testing repetition
 
-use self::config::FactsConfig;
-use anyhow::bail;
-use rand::seq::SliceRandom;
-use tracing::{instrument, trace};
+#[cfg(feature = "wasm")]
+use self::adapters::wasm::Plugins;
+use self::adapters::NoFacts;
+use self::auth::Authenticator;
+use self::config::{AdapterConfig, ExtractConfig, FactsConfig, SourceConfig};
+use self::corpus::{FileCorpus, FILE_SCHEME};
+use self::filter::{filter_facts, FactsRejected};
+use self::flight::SingleFlight;
+use self::hedge::Hedging;
+use self::picker::AnimalPicker;
+use self::prefetch::PrefetchedFacts;
+use self::recent::RecentFacts;
+use self::schema::{ContractBroken, SchemaValidator};
+use self::seen::SeenFacts;
+use self::sources::SourceBalancer;
+use self::store::{
+  content_hash, FactStore, SearchCursor, SearchHit, StoredFact,
+};
+use self::template::{process_env, EndpointRequest, EnvLookup, TemplateVars};
+use anyhow::{anyhow, bail};
+use chrono::NaiveDate;
+use hyper::body::Bytes;
+use reqwest::{
+  header::{HeaderMap, RETRY_AFTER},
+  StatusCode,
+};
+use std::{
+  collections::HashMap,
+  future::Future,
+  path::PathBuf,
+  time::{Duration, Instant},
+};
+use tracing::{instrument, trace, warn};
 
 pub mod adapters;
+pub mod auth;
 pub mod config;
+pub mod corpus;
+pub mod filter;
+pub mod flight;
+pub mod hedge;
+pub mod picker;
+pub mod prefetch;
+pub mod recent;
+pub mod schema;
+pub mod seen;
+pub mod sources;
+pub mod store;
+pub mod template;
 
-/// Fact transform adapters should implement the Transformable trait
+/// Maximum number of the facts retained in memory when there is no fact store
+/// configured. It deliberately limits the memory of the in-memory fallback, so
+/// only the latest fetched facts are searchable and the older ones are pruned.
+/// Configure the fact store to search all the fetched facts.
+const RETAINED_CAPACITY: usize = 10000;
+
+/// Fact transform adapters should implement the Transformable trait. Adapters
+/// receive the whole endpoint response and may be asynchronous, so they can
+/// inspect the status and headers or follow up with another request.
 pub trait Transformable {
-  fn transform(&self, response_content: &str) -> anyhow::Result<String>;
+  fn transform(
+    &self,
+    response: &ResponseContext,
+  ) -> impl Future<Output = anyhow::Result<Vec<Fact>>> + Send;
+}
+
+/// Fact returned by the adapters with the metadata provided by the endpoint
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fact {
+  pub text: String,
+  /// Id of the fact at the endpoint
+  pub id: Option<String>,
+  /// URL the fact comes from, the endpoint response URL unless the adapter
+  /// provides one
+  pub source: Option<String>,
+  /// Time the fact was created at the endpoint, formatted as the endpoint does
+  pub created_at: Option<String>,
+  /// Language code of the fact text
+  pub language: Option<String>,
+  pub tags: Vec<String>,
+  /// Author or origin of the fact to credit
+  pub attribution: Option<String>,
+}
+
+impl Fact {
+  /// Fact with the text and without any metadata
+  pub fn new(text: impl Into<String>) -> Self {
+    Fact {
+      text: text.into(),
+      ..Default::default()
+    }
+  }
+
+  /// Fact with the text coming from the source URL
+  fn from_source(text: impl Into<String>, source: &str) -> Self {
+    Fact {
+      source: Some(source.to_string()),
+      ..Fact::new(text)
+    }
+  }
+}
+
+/// Endpoint response passed to the adapters
+#[derive(Debug, Clone)]
+pub struct ResponseContext {
+  pub status: StatusCode,
+  pub headers: HeaderMap,
+  /// Final URL of the response after following the redirects
+  pub url: String,
+  /// Time from sending the request until the whole body is received
+  pub latency: Duration,
+  pub body: Bytes,
+}
+
+impl ResponseContext {
+  /// Successful response with the body, as served by the endpoint at the URL
+  #[cfg(test)]
+  pub fn new(url: &str, body: impl Into<Bytes>) -> Self {
+    ResponseContext {
+      status: StatusCode::OK,
+      headers: HeaderMap::new(),
+      url: url.to_string(),
+      latency: Duration::ZERO,
+      body: body.into(),
+    }
+  }
+
+  /// Response body as the UTF-8 text
+  pub fn text(&self) -> anyhow::Result<&str> {
+    Ok(std::str::from_utf8(&self.body)?)
+  }
+
+  /// Response body as the UTF-8 text, failing on the non-successful status
+  pub fn success_text(&self) -> anyhow::Result<&str> {
+    if !self.status.is_success() {
+      match self.headers.get(RETRY_AFTER).and_then(|v| v.to_str().ok()) {
+        Some(retry_after) => bail!(
+          "endpoint responded with the status: {}, retry after: {}",
+          self.status,
+          retry_after
+        ),
+        None => bail!("endpoint responded with the status: {}", self.status),
+      }
+    }
+    self.text()
+  }
+}
+
+/// Fact resolved by the `FactResolver`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedFact {
+  pub animal: String,
+  pub fact: Fact,
+  /// The fact was not freshly fetched but served from the recent facts or the
+  /// store because the endpoint request failed
+  pub stale: bool,
+  /// The client has seen the fact recently, but no other fact was resolved
+  /// within the re-fetch limit
+  pub repeated: bool,
+}
+
+/// Per-request values of the `{{count}}` and `{{language}}` template variables
+/// of the endpoint requests
+#[derive(Debug, Clone, Copy)]
+struct FactQuery<'a> {
+  count: usize,
+  language: Option<&'a str>,
 }
 
 #[derive(Debug, Clone)]
 pub struct FactResolver {
   config: FactsConfig,
+  store: Option<FactStore>,
+  corpus: FileCorpus,
+  recent: RecentFacts,
+  sources: HashMap<String, SourceBalancer>,
+  picker: AnimalPicker,
+  /// Store retaining the fetched facts for the search and the pinned daily
+  /// facts, the configured store or the capped in-memory one when there is no
+  /// store configured. `None` when the in-memory store failed to open.
+  retained: Option<FactStore>,
+  seen: SeenFacts,
+  prefetched: PrefetchedFacts,
+  #[cfg(feature = "wasm")]
+  plugins: Plugins,
+  schemas: SchemaValidator,
+  http: reqwest::Client,
+  auth: Authenticator,
+  flights: SingleFlight<ResponseContext>,
+  hedging: Option<Hedging>,
+  /// Environment variables lookup of the `${NAME}` templates
+  env: EnvLookup,
 }
 
 impl FactResolver {
@@ -32,59 +208,234 @@ impl FactResolver {
     FactResolver {
       // We can possibly remove this clonning by using a lifitime attribute <'a>
       config: config.clone(),
+      store: None,
+      corpus: FileCorpus::default(),
+      recent: RecentFacts::new(
+        config.stale.capacity,
+        Duration::from_secs(config.stale.max_staleness),
+      ),
+      sources: config
+        .facts
+        .iter()
+        .map(|(animal, animal_config)| {
+          (
+            animal.clone(),
+            SourceBalancer::new(animal_config.to_sources()),
+          )
+        })
+        .collect(),
+      picker: AnimalPicker::new(
+        &config.facts.keys().cloned().collect::<Vec<_>>(),
+        &config.any,
+      ),
+      retained: match FactStore::open_in_memory() {
+        Ok(store) => Some(store.with_capacity(RETAINED_CAPACITY)),
+        Err(err) => {
+          warn!("in-memory fact store opening error: {}", err);
+          None
+        }
+      },
+      seen: SeenFacts::new(config.dedup.history, config.dedup.max_clients),
+      prefetched: PrefetchedFacts::new(
+        config.prefetch.capacity,
+        Duration::from_secs(config.prefetch.max_age),
+      ),
+      #[cfg(feature = "wasm")]
+      plugins: Plugins::default(),
+      schemas: SchemaValidator::default(),
+      http: reqwest::Client::new(),
+      auth: Authenticator::default(),
+      flights: SingleFlight::default(),
+      hedging: config.hedge.clone().map(Hedging::new),
+      env: process_env,
     }
   }
 
-  /// Making a request to the API endpoint by shooting a GET reqwest and returning
-  /// the response body as a string.
-  #[instrument]
-  async fn request_api(&self, endpoint: &str) -> anyhow::Result<String> {
-    trace!("requesting dogs endpoint: {}", endpoint);
-    let content = reqwest::get(endpoint).await?.text().await?;
-    Ok(content)
+  /// Persisting every resolved fact into the store and serving the stored
+  /// facts on the endpoint failures when configured
+  pub fn with_store(mut self, store: FactStore) -> Self {
+    self.retained = Some(store.clone());
+    self.store = Some(store);
+    self
+  }
+
+  /// Looking up the `${NAME}` template variables by the function instead of
+  /// the process environment
+  #[cfg(test)]
+  pub fn with_env(mut self, env: EnvLookup) -> Self {
+    self.env = env;
+    self
+  }
+
+  /// Making a request to the API endpoint by shooting the rendered request and
+  /// returning the response with its status, headers and body. Coalesced
+  /// requests share the response of the concurrent same request, hedged ones
+  /// only of the concurrent hedged request, so a hedge never joins the slow
+  /// request it hedges.
+  #[instrument(skip(self, endpoint), fields(endpoint = %endpoint.redacted()))]
+  async fn request_api(
+    &self,
+    endpoint: EndpointRequest,
+    coalesce: bool,
+    hedged: bool,
+  ) -> anyhow::Result<ResponseContext> {
+    trace!("requesting the endpoint: {}", endpoint.redacted());
+    let key = match hedged {
+      true => format!("hedge {}", endpoint.key()),
+      false => endpoint.key(),
+    };
+    let http = self.http.clone();
+    let authenticator = self.auth.clone();
+    let request = async move {
+      let started = Instant::now();
+      let send = || async {
+        let mut request = endpoint.builder(&http);
+        let mut token = None;
+        if let Some(auth) = &endpoint.auth {
+          (request, token) =
+            authenticator.authorize(&http, request, auth).await?;
+        }
+        anyhow::Ok((request.send().await?, token))
+      };
+      let (mut response, token) = send().await?;
+      // The access token may be revoked before it expires, so it's requested
+      // again once when the endpoint rejects it
+      if let (Some(auth), Some(token)) = (&endpoint.auth, &token) {
+        if response.status() == StatusCode::UNAUTHORIZED {
+          authenticator.invalidate(auth, token).await;
+          response = send().await?.0;
+        }
+      }
+      let status = response.status();
+      let headers = response.headers().clone();
+      let url = response.url().to_string();
+      let body = response.bytes().await?;
+      Ok(ResponseContext {
+        status,
+        headers,
+        url,
+        latency: started.elapsed(),
+        body,
+      })
+    };
+    if coalesce {
+      self.flights.run(&key, request).await
+    } else {
+      request.await
+    }
   }
 
   /// Calling the transform method of the given adapter
-  fn transfrom(
+  async fn transfrom(
     &self,
     adapter: &impl Transformable,
-    input: &str,
-  ) -> anyhow::Result<String> {
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
     trace!("transforming imput using adapter");
-    adapter.transform(input)
+    adapter.transform(response).await
   }
 
-  /// Choose which adapter to use based on the animal name and check if that adapter is
-  /// present
-  #[instrument]
-  fn use_adapter(
+  /// Choose which adapter to use based on the adapter name and check if that adapter is
+  /// present, or the generic adapter of the response format
+  #[instrument(skip(response))]
+  async fn use_adapter(
     &self,
-    animal_type: &str,
-    input: &str,
-  ) -> anyhow::Result<String> {
-    trace!("choosing adapter based on the animal type: {}", animal_type);
-    match animal_type {
-      "dog" => self.transfrom(&adapters::dog::Adapter, input),
-      "cat" => self.transfrom(&adapters::cat::Adapter, input),
-      _ => bail!("invalid animal type: {}", animal_type),
+    adapter: &AdapterConfig,
+    response: &ResponseContext,
+  ) -> anyhow::Result<Vec<Fact>> {
+    let extract = match adapter {
+      AdapterConfig::Named(name) => {
+        trace!("choosing adapter based on the name: {}", name);
+        return match name.as_str() {
+          "dog" => self.transfrom(&adapters::dog::Adapter, response).await,
+          "cat" => self.transfrom(&adapters::cat::Adapter, response).await,
+          _ => bail!("invalid adapter: {}", name),
+        };
+      }
+      AdapterConfig::Extract(extract) => extract,
+    };
+    match extract {
+      ExtractConfig::Text(config) => {
+        let adapter = adapters::text::Adapter::new(config);
+        self.transfrom(&adapter, response).await
+      }
+      ExtractConfig::Xml(config) => {
+        let adapter = adapters::xml::Adapter::new(config);
+        self.transfrom(&adapter, response).await
+      }
+      ExtractConfig::Csv(config) => {
+        let adapter = adapters::csv::Adapter::new(config);
+        self.transfrom(&adapter, response).await
+      }
+      ExtractConfig::Html(config) => {
+        let adapter = adapters::html::Adapter::new(config);
+        self.transfrom(&adapter, response).await
+      }
+      ExtractConfig::Script(config) => {
+        let adapter = adapters::script::Adapter::new(config);
+        self.transfrom(&adapter, response).await
+      }
+      #[cfg(feature = "wasm")]
+      ExtractConfig::Wasm(config) => {
+        let adapter = adapters::wasm::Adapter::new(config, &self.plugins);
+        self.transfrom(&adapter, response).await
+      }
+      #[cfg(not(feature = "wasm"))]
+      ExtractConfig::Wasm(config) => bail!(
+        "plugin adapter {} needs the server built with the wasm feature",
+        config.path
+      ),
     }
   }
 
+  /// Names of the animals configured in the facts configuration, sorted
+  /// alphabetically.
+  pub fn animals(&self) -> Vec<String> {
+    let mut animals: Vec<String> = self.config.facts.keys().cloned().collect();
+    animals.sort();
+    animals
+  }
+
   /// Getting the fact from the remote endpoint for the animal based
   /// on the configuration parameters.
-  /// Result is a tuple with the animal type and the animal fact text.
   #[instrument]
-  pub async fn get_fact(&self) -> anyhow::Result<(String, String)> {
+  pub async fn get_fact(&self) -> anyhow::Result<ResolvedFact> {
     trace!("getting the animal fact");
-    let animal_fact = if self.config.default == "any" {
-      vec!["dog".to_string(), "cat".to_string()]
-        .choose(&mut rand::thread_rng())
-        .unwrap()
-        .clone()
-    } else {
-      self.config.default.clone()
-    };
+    let animal_fact = self.default_animal()?;
+    self.get_animal_fact(&animal_fact).await
+  }
 
+  /// Getting the fact from the remote endpoint for the given animal type.
+  /// When the request fails, a recently resolved fact or a stored one is served
+  /// marked as stale and the animal is refreshed in the background.
+  #[instrument]
+  pub async fn get_animal_fact(
+    &self,
+    animal_fact: &str,
+  ) -> anyhow::Result<ResolvedFact> {
+    trace!("getting the fact for the animal: {}", animal_fact);
+    let mut resolved = self.get_facts(Some(animal_fact), 1, None).await?;
+    resolved.pop().ok_or_else(|| NoFacts.into())
+  }
+
+  /// Getting several facts of the animal, or of the default one when omitted,
+  /// in the language when given. The sources are requested with the count and
+  /// language as the `{{count}}` and `{{language}}` template variables, so the
+  /// endpoints returning several facts per response are requested once. When
+  /// the request fails, the missing facts are the distinct recent ones marked
+  /// as stale, so fewer facts than requested may be returned.
+  #[instrument]
+  pub async fn get_facts(
+    &self,
+    animal_fact: Option<&str>,
+    count: usize,
+    language: Option<&str>,
+  ) -> anyhow::Result<Vec<ResolvedFact>> {
+    let animal_fact = match animal_fact {
+      Some(animal_fact) => animal_fact.to_string(),
+      None => self.default_animal()?,
+    };
+    trace!("getting {} facts for the animal: {}", count, animal_fact);
     // Check if the configuration contains the animal type
     if !self.config.facts.contains_key(&animal_fact) {
       bail!(
@@ -93,10 +444,522 @@ impl FactResolver {
       );
     }
 
-    let endpoint_api = self.config.facts[&animal_fact].clone();
-    let response = self.request_api(&endpoint_api).await?;
-    let fact_text = self.use_adapter(&animal_fact, &response)?;
+    // Prefetched facts are fetched in the configured language only
+    let configured = language.map_or(true, |language| {
+      self.config.language.as_deref() == Some(language)
+    });
+    let fresh = |fact| ResolvedFact {
+      animal: animal_fact.clone(),
+      fact,
+      stale: false,
+      repeated: false,
+    };
+    let mut facts = Vec::with_capacity(count);
+    while configured && facts.len() < count {
+      let Some(fact) = self.prefetched.pop(&animal_fact) else {
+        break;
+      };
+      trace!("serving the prefetched fact");
+      facts.push(fresh(fact));
+    }
 
-    Ok((animal_fact, fact_text))
+    while facts.len() < count {
+      let missing = count - facts.len();
+      let query = FactQuery {
+        count: match configured {
+          true => missing.max(self.config.prefetch.batch),
+          false => missing,
+        },
+        language: language.or(self.config.language.as_deref()),
+      };
+      match self.fetch_fact(&animal_fact, query).await {
+        Ok(fetched) => {
+          let mut fetched = fetched.into_iter();
+          for fact in fetched.by_ref().take(missing) {
+            facts.push(fresh(fact));
+          }
+          if configured {
+            self.prefetched.push(&animal_fact, fetched);
+          }
+        }
+        Err(err) => {
+          let missing = count - facts.len();
+          facts.extend(self.stale_facts(&animal_fact, missing, err).await?);
+          break;
+        }
+      }
+    }
+    for resolved in facts.iter().filter(|resolved| !resolved.stale) {
+      self.recent.remember(&animal_fact, &resolved.fact);
+    }
+    Ok(facts)
+  }
+
+  /// Animal of the request without one: the configured default animal, or the
+  /// randomly picked one when the default is `any`
+  fn default_animal(&self) -> anyhow::Result<String> {
+    if self.config.default != "any" {
+      return Ok(self.config.default.clone());
+    }
+    self
+      .picker
+      .pick()
+      .ok_or_else(|| anyhow!("no animals to pick for the `any` default"))
+  }
+
+  /// Distinct recently resolved facts of the animal up to the count, or a
+  /// stored one when there are none, marked as stale after the endpoint error,
+  /// refreshing the animal in the background. The error is returned when there
+  /// is no such fact, and for the broken upstream contract or the facts
+  /// rejected by the filter, so the upstream drift is not masked by the stale
+  /// facts.
+  async fn stale_facts(
+    &self,
+    animal_fact: &str,
+    count: usize,
+    err: anyhow::Error,
+  ) -> anyhow::Result<Vec<ResolvedFact>> {
+    if err.downcast_ref::<ContractBroken>().is_some()
+      || err.downcast_ref::<FactsRejected>().is_some()
+    {
+      return Err(err);
+    }
+    let mut stale_facts = self.recent.recent(animal_fact, count);
+    if stale_facts.is_empty() {
+      if let Some(stored) = self.stored_fact(animal_fact).await {
+        stale_facts.push(Fact::from_source(stored.text, &stored.endpoint));
+      }
+    }
+    if stale_facts.is_empty() {
+      return Err(err);
+    }
+    warn!("serving the stale facts after the endpoint error: {}", err);
+    self.refresh_in_background(animal_fact);
+    Ok(
+      stale_facts
+        .into_iter()
+        .map(|fact| ResolvedFact {
+          animal: animal_fact.to_string(),
+          fact,
+          stale: true,
+          repeated: false,
+        })
+        .collect(),
+    )
+  }
+
+  /// Getting the fact of the animal, or of the default one when omitted, that
+  /// the client has not seen in its last responses. Repeated facts are
+  /// re-fetched up to the configured limit, then the repeat is returned marked
+  /// as `repeated`.
+  #[instrument]
+  pub async fn get_client_fact(
+    &self,
+    animal_fact: Option<&str>,
+    client: &str,
+  ) -> anyhow::Result<ResolvedFact> {
+    let resolve = || async {
+      match animal_fact {
+        Some(animal_fact) => self.get_animal_fact(animal_fact).await,
+        None => self.get_fact().await,
+      }
+    };
+    let mut resolved = resolve().await?;
+    if !self.seen.enabled() {
+      return Ok(resolved);
+    }
+
+    let mut refetches = 0;
+    while self.seen.contains(client, &resolved.fact.text) {
+      if refetches == self.config.dedup.max_refetches {
+        trace!("falling back to the repeated fact");
+        resolved.repeated = true;
+        break;
+      }
+      trace!("re-fetching the fact seen by the client");
+      refetches += 1;
+      resolved = resolve().await?;
+    }
+    self.seen.record(client, &resolved.fact.text);
+    Ok(resolved)
+  }
+
+  /// Numbers of the hedged requests and of the ones finishing first
+  #[cfg(test)]
+  pub fn hedge_counts(&self) -> Option<(u64, u64)> {
+    self.hedging.as_ref().map(Hedging::counts)
+  }
+
+  /// Number of the endpoint responses schema violations
+  #[cfg(test)]
+  pub fn schema_violations(&self) -> u64 {
+    self.schemas.violations()
+  }
+
+  /// Full-text search over all the facts fetched through the resolver, the
+  /// most relevant first, starting after the cursor
+  #[instrument]
+  pub async fn search(
+    &self,
+    query: &str,
+    animal: Option<&str>,
+    after: Option<SearchCursor>,
+    limit: usize,
+  ) -> anyhow::Result<Vec<SearchHit>> {
+    let Some(store) = self.retained.clone() else {
+      bail!("the fact search is unavailable without the fact store");
+    };
+    let query = query.to_string();
+    let animal = animal.map(str::to_string);
+    tokio::task::spawn_blocking(move || {
+      store.search(&query, animal.as_deref(), after, limit)
+    })
+    .await?
+  }
+
+  /// Animal of the daily fact when the request does not specify one. It is
+  /// the default animal or, when the default is `any`, the animal chosen
+  /// deterministically for the date.
+  pub fn daily_animal(&self, date: NaiveDate) -> Option<String> {
+    if self.config.default != "any" {
+      return Some(self.config.default.clone());
+    }
+    let animals = self.animals();
+    let hash = content_hash(&date.to_string());
+    let index = u64::from_str_radix(&hash[..16], 16).ok()?;
+    animals
+      .get((index % animals.len().max(1) as u64) as usize)
+      .cloned()
+  }
+
+  /// Fact of the day of the animal, the same for everybody on the date. It is
+  /// chosen deterministically from the stored facts, or fetched once when there
+  /// are none, and pinned for the date in the store. The stale fallback fact is
+  /// returned without pinning, so the date gets a fresh fact later.
+  #[instrument]
+  pub async fn daily_fact(
+    &self,
+    animal_fact: &str,
+    date: NaiveDate,
+  ) -> anyhow::Result<ResolvedFact> {
+    trace!("getting the daily fact for the animal: {}", animal_fact);
+    if !self.config.facts.contains_key(animal_fact) {
+      bail!(
+        "the animal type does not exist in the configuration file: {}",
+        animal_fact
+      );
+    }
+
+    let Some(retained) = self.retained.clone() else {
+      warn!("no fact store to pin the daily fact, fetching it");
+      return self.get_animal_fact(animal_fact).await;
+    };
+    let date = date.to_string();
+    let (store, animal, day) =
+      (retained.clone(), animal_fact.to_string(), date.clone());
+    let chosen = tokio::task::spawn_blocking(move || {
+      match store.pinned(&animal, &day)? {
+        Some(fact) => Ok(Some(fact)),
+        None => store
+          .daily_candidate(&animal, &day)?
+          .map(|fact| store.pin(&animal, &day, &fact))
+          .transpose(),
+      }
+    })
+    .await??;
+
+    let fact = match chosen {
+      Some(fact) => fact,
+      None => {
+        trace!("no stored facts to choose from, fetching the daily fact");
+        let fetched = self.get_animal_fact(animal_fact).await?;
+        if fetched.stale {
+          warn!("not pinning the stale daily fact of the date: {}", date);
+          return Ok(fetched);
+        }
+        let animal = animal_fact.to_string();
+        tokio::task::spawn_blocking(move || {
+          retained.pin(&animal, &date, &fetched.fact.text)
+        })
+        .await??
+      }
+    };
+    Ok(ResolvedFact {
+      animal: animal_fact.to_string(),
+      fact: Fact::new(fact),
+      stale: false,
+      repeated: false,
+    })
+  }
+
+  /// Fetching the fact of the animal in the background task to refresh the
+  /// recent facts, unless there is a refresh of the animal in flight already
+  fn refresh_in_background(&self, animal_fact: &str) {
+    if !self.recent.start_refresh(animal_fact) {
+      return;
+    }
+    let fact_resolver = self.clone();
+    let animal_fact = animal_fact.to_string();
+    tokio::spawn(async move {
+      trace!("refreshing the animal fact in background: {}", animal_fact);
+      let query = fact_resolver.configured_query();
+      match fact_resolver.fetch_fact(&animal_fact, query).await {
+        Ok(fetched) => {
+          let mut fetched = fetched.into_iter();
+          if let Some(fact) = fetched.next() {
+            fact_resolver.recent.remember(&animal_fact, &fact);
+          }
+          fact_resolver.prefetched.push(&animal_fact, fetched);
+        }
+        Err(err) => warn!("background refresh failed: {}", err),
+      }
+      fact_resolver.recent.finish_refresh(&animal_fact);
+    });
+  }
+
+  /// Template variables of the requests in the configured language with the
+  /// configured prefetch batch
+  fn configured_query(&self) -> FactQuery<'_> {
+    FactQuery {
+      count: self.config.prefetch.batch,
+      language: self.config.language.as_deref(),
+    }
+  }
+
+  /// Fetching the facts of the animal, fetching again when every fact of the
+  /// response is rejected by the animal filter, up to its `max_refetches`
+  async fn fetch_fact(
+    &self,
+    animal_fact: &str,
+    query: FactQuery<'_>,
+  ) -> anyhow::Result<Vec<Fact>> {
+    let max_refetches = self
+      .config
+      .filters
+      .get(animal_fact)
+      .map_or(0, |filter| filter.max_refetches);
+    let mut refetches = 0;
+    loop {
+      match self.fetch_sources(animal_fact, query).await {
+        Err(err)
+          if refetches < max_refetches
+            && err.downcast_ref::<FactsRejected>().is_some() =>
+        {
+          refetches += 1;
+          trace!("refetching the rejected facts: {}", err);
+        }
+        result => return result,
+      }
+    }
+  }
+
+  /// Fetching the facts from the animal sources in the order of the
+  /// configured strategy, trying the next source when the previous one fails
+  async fn fetch_sources(
+    &self,
+    animal_fact: &str,
+    query: FactQuery<'_>,
+  ) -> anyhow::Result<Vec<Fact>> {
+    let balancer = self
+      .sources
+      .get(animal_fact)
+      .ok_or_else(|| anyhow!("no sources of the animal: {}", animal_fact))?;
+
+    let mut last_err = anyhow!("no sources of the animal: {}", animal_fact);
+    let order = balancer.order();
+    for (position, index) in order.iter().copied().enumerate() {
+      let hedge_index = match &self.hedging {
+        Some(hedging) if hedging.alternate() => {
+          order.get(position + 1).copied().unwrap_or(index)
+        }
+        _ => index,
+      };
+      match self
+        .fetch_attempt(animal_fact, query, balancer, index, hedge_index)
+        .await
+      {
+        Ok(facts) => return Ok(facts),
+        Err(err) => last_err = err,
+      }
+    }
+    Err(last_err)
+  }
+
+  /// Fetching the facts from the source. When hedging is configured and the
+  /// request has not completed after the delay, the hedged request is sent to
+  /// the hedge source and the first successful response is used, cancelling
+  /// the other request. The hedging latencies are measured from the start of
+  /// the first request.
+  async fn fetch_attempt(
+    &self,
+    animal_fact: &str,
+    query: FactQuery<'_>,
+    balancer: &SourceBalancer,
+    index: usize,
+    hedge_index: usize,
+  ) -> anyhow::Result<Vec<Fact>> {
+    let started = Instant::now();
+    let request = |index: usize, hedged: bool| async move {
+      let started = Instant::now();
+      let source = balancer.source(index);
+      let result = self.fetch_source(animal_fact, query, source, hedged).await;
+      (index, started.elapsed(), result)
+    };
+
+    let primary = request(index, false);
+    let hedge = self
+      .hedging
+      .as_ref()
+      .and_then(|hedging| Some((hedging, hedging.delay(animal_fact)?)));
+    let (index, latency, result) = match hedge {
+      None => primary.await,
+      Some((hedging, delay)) => {
+        tokio::pin!(primary);
+        tokio::select! {
+          outcome = &mut primary => outcome,
+          _ = tokio::time::sleep(delay) => {
+            hedging.record_hedge(animal_fact, delay);
+            let hedged = request(hedge_index, true);
+            tokio::pin!(hedged);
+            // Waiting for the other request when the first one fails
+            tokio::select! {
+              outcome = &mut primary => match outcome {
+                (_, _, Err(_)) => {
+                  let outcome = hedged.await;
+                  if outcome.2.is_ok() {
+                    hedging.record_win(animal_fact);
+                  }
+                  outcome
+                }
+                outcome => outcome,
+              },
+              outcome = &mut hedged => match outcome {
+                (_, _, Err(_)) => primary.await,
+                outcome => {
+                  hedging.record_win(animal_fact);
+                  outcome
+                }
+              },
+            }
+          }
+        }
+      }
+    };
+
+    match result {
+      Ok(facts) => {
+        balancer.record_success(index, latency);
+        if let Some(hedging) = &self.hedging {
+          hedging.record_latency(animal_fact, started.elapsed());
+        }
+        Ok(facts)
+      }
+      Err(err) => {
+        let source = balancer.source(index);
+        warn!("fact source {} failed: {}", source.url, err);
+        balancer.record_failure(index);
+        Err(err)
+      }
+    }
+  }
+
+  /// Requesting the endpoint, transforming the response by the adapter and
+  /// persisting the result into the retained facts store. The facts of the
+  /// response are returned, at least one. Endpoints with the `file://` scheme
+  /// are served from the local corpus files instead.
+  async fn fetch_source(
+    &self,
+    animal_fact: &str,
+    query: FactQuery<'_>,
+    source: &SourceConfig,
+    hedged: bool,
+  ) -> anyhow::Result<Vec<Fact>> {
+    let endpoint_api = source.url.as_str();
+    if let Some(path) = endpoint_api.strip_prefix(FILE_SCHEME) {
+      trace!("reading the corpus file: {}", path);
+      let corpus = self.corpus.clone();
+      let path = PathBuf::from(path);
+      let text = tokio::task::spawn_blocking(move || corpus.random_fact(&path))
+        .await??;
+      let fact = Fact::from_source(text, endpoint_api);
+      return self.filter(animal_fact, vec![fact]);
+    }
+
+    let coalesce = source.coalesce.unwrap_or(self.config.coalesce);
+    let vars = TemplateVars {
+      animal: animal_fact,
+      count: query.count,
+      language: query.language,
+      env: self.env,
+    };
+    let endpoint = EndpointRequest::render(source, &vars)?;
+    let response = self.request_api(endpoint, coalesce, hedged).await?;
+    trace!(
+      "received the response from {} in {:?}",
+      source.url,
+      response.latency
+    );
+    if let Some(schema) = &source.schema {
+      self.schemas.validate(schema, &response).await?;
+    }
+    let named = AdapterConfig::Named(animal_fact.to_string());
+    let adapter = source.adapter.as_ref().unwrap_or(&named);
+    let facts = self.use_adapter(adapter, &response).await?;
+    if facts.is_empty() {
+      return Err(NoFacts.into());
+    }
+    let mut facts = self.filter(animal_fact, facts)?;
+    for fact in &mut facts {
+      fact.source.get_or_insert_with(|| response.url.clone());
+    }
+
+    if let Some(store) = self.retained.clone() {
+      let stored: Vec<StoredFact> = facts
+        .iter()
+        .map(|fact| StoredFact::new(animal_fact, &fact.text, endpoint_api))
+        .collect();
+      let result = tokio::task::spawn_blocking(move || {
+        stored.iter().try_for_each(|fact| store.insert(fact))
+      })
+      .await;
+      if let Err(err) = result.map_err(anyhow::Error::from).and_then(|r| r) {
+        warn!("failed to persist the fact: {}", err);
+      }
+    }
+
+    Ok(facts)
+  }
+
+  /// Facts passing the filter of the animal when configured
+  fn filter(
+    &self,
+    animal_fact: &str,
+    facts: Vec<Fact>,
+  ) -> anyhow::Result<Vec<Fact>> {
+    match self.config.filters.get(animal_fact) {
+      Some(filter) => filter_facts(filter, animal_fact, facts),
+      None => Ok(facts),
+    }
+  }
+
+  /// Random stored fact of the animal when serving from the store on the
+  /// endpoint failures is configured
+  async fn stored_fact(&self, animal_fact: &str) -> Option<StoredFact> {
+    let serve_on_failure = self
+      .config
+      .store
+      .as_ref()
+      .map_or(true, |store| store.serve_on_failure);
+    let store = self.store.clone().filter(|_| serve_on_failure)?;
+    let animal_fact = animal_fact.to_string();
+    let result =
+      tokio::task::spawn_blocking(move || store.random(&animal_fact)).await;
+    match result.map_err(anyhow::Error::from).and_then(|fact| fact) {
+      Ok(fact) => fact,
+      Err(err) => {
+        warn!("failed to read the stored fact: {}", err);
+        None
+      }
+    }
   }
 }
@@ -0,0 +1,171 @@
+//! Animal picker of the `any` default
+//!
+//! Picking a random configured animal by the configured weights, skipping the
+//! excluded animals and the animal picked too many times in a row. The random
+//! generator can be seeded to make the picks deterministic.
+
+use super::config::AnyConfig;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::sync::{Arc, Mutex};
+
+/// Random generator and the picks history shared between the resolver clones
+#[derive(Debug)]
+struct PickerState {
+  rng: StdRng,
+  last: Option<String>,
+  repeats: usize,
+}
+
+/// Weighted random animal picker
+#[derive(Debug, Clone)]
+pub struct AnimalPicker {
+  /// Animals available for picking with their weights, sorted by the name
+  animals: Vec<(String, u32)>,
+  max_repeats: usize,
+  state: Arc<Mutex<PickerState>>,
+}
+
+impl AnimalPicker {
+  pub fn new(animals: &[String], config: &AnyConfig) -> Self {
+    let mut animals: Vec<(String, u32)> = animals
+      .iter()
+      .filter(|animal| !config.exclude.contains(animal))
+      .map(|animal| {
+        (
+          animal.clone(),
+          config.weights.get(animal).copied().unwrap_or(1),
+        )
+      })
+      .filter(|(_, weight)| *weight > 0)
+      .collect();
+    animals.sort();
+    let rng = match config.seed {
+      Some(seed) => StdRng::seed_from_u64(seed),
+      None => StdRng::from_entropy(),
+    };
+    AnimalPicker {
+      animals,
+      max_repeats: config.max_repeats,
+      state: Arc::new(Mutex::new(PickerState {
+        rng,
+        last: None,
+        repeats: 0,
+      })),
+    }
+  }
+
+  /// Picking the next animal, `None` when there are no animals to pick
+  pub fn pick(&self) -> Option<String> {
+    let mut state = self.state.lock().unwrap();
+    let state = &mut *state;
+    let repeat_limited =
+      self.max_repeats > 0 && state.repeats >= self.max_repeats;
+    let candidates: Vec<&(String, u32)> = self
+      .animals
+      .iter()
+      .filter(|(animal, _)| {
+        !repeat_limited || state.last.as_ref() != Some(animal)
+      })
+      .collect();
+    // The only available animal is repeated whatever the limit is
+    let candidates = if candidates.is_empty() {
+      self.animals.iter().collect()
+    } else {
+      candidates
+    };
+
+    let (animal, _) = candidates
+      .choose_weighted(&mut state.rng, |(_, weight)| *weight)
+      .ok()?;
+    if state.last.as_ref() == Some(animal) {
+      state.repeats += 1;
+    } else {
+      state.last = Some(animal.clone());
+      state.repeats = 1;
+    }
+    Some(animal.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+
+  fn animals() -> Vec<String> {
+    vec!["cat".to_string(), "dog".to_string(), "fox".to_string()]
+  }
+
+  /// Picks should follow the weights and never return the excluded animals
+  #[test]
+  fn weights_and_exclusions() {
+    let picker = AnimalPicker::new(
+      &animals(),
+      &AnyConfig {
+        weights: HashMap::from([
+          ("cat".to_string(), 7),
+          ("dog".to_string(), 3),
+        ]),
+        exclude: vec!["fox".to_string()],
+        seed: Some(7),
+        ..Default::default()
+      },
+    );
+
+    let cats = (0..1000)
+      .map(|_| picker.pick().unwrap())
+      .inspect(|animal| assert_ne!(animal, "fox", "excluded animal picked"))
+      .filter(|animal| animal == "cat")
+      .count();
+    assert!((620..780).contains(&cats), "unexpected cats count {}", cats);
+  }
+
+  /// The same animal should not be picked more than the max repeats in a row
+  /// unless it is the only one
+  #[test]
+  fn max_repeats() {
+    let picker = AnimalPicker::new(
+      &animals(),
+      &AnyConfig {
+        weights: HashMap::from([("dog".to_string(), 100)]),
+        max_repeats: 2,
+        ..Default::default()
+      },
+    );
+    let picks: Vec<String> = (0..100).map(|_| picker.pick().unwrap()).collect();
+    for window in picks.windows(3) {
+      assert!(
+        window[0] != window[1] || window[1] != window[2],
+        "animal picked more than twice in a row: {:?}",
+        window
+      );
+    }
+
+    let picker = AnimalPicker::new(
+      &animals(),
+      &AnyConfig {
+        exclude: vec!["cat".to_string(), "fox".to_string()],
+        max_repeats: 1,
+        ..Default::default()
+      },
+    );
+    assert_eq!(picker.pick().as_deref(), Some("dog"));
+    assert_eq!(picker.pick().as_deref(), Some("dog"));
+  }
+
+  /// The same seed should produce the same picks, no animals produce none
+  #[test]
+  fn seeded_picks() {
+    let config = AnyConfig {
+      seed: Some(42),
+      ..Default::default()
+    };
+    let first = AnimalPicker::new(&animals(), &config);
+    let second = AnimalPicker::new(&animals(), &config);
+    for _n in 0..100 {
+      assert_eq!(first.pick(), second.pick());
+    }
+
+    assert!(AnimalPicker::new(&[], &config).pick().is_none());
+  }
+}
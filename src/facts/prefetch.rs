@@ -0,0 +1,94 @@
+//! Prefetched facts
+//!
+//! Endpoints like the dog one with `?number=N` return several facts per
+//! response. The resolver returns the first fact and keeps the extra ones here,
+//! serving them to the next requests of the animal before requesting the
+//! endpoint again. The facts older than the max age are dropped.
+
+use super::Fact;
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+/// Prefetched facts of an animal with the time they were fetched, oldest first
+type AnimalFacts = VecDeque<(Instant, Fact)>;
+
+/// Prefetched facts of the animals shared between the resolver clones
+#[derive(Debug, Clone)]
+pub struct PrefetchedFacts {
+  capacity: usize,
+  max_age: Duration,
+  facts: Arc<Mutex<HashMap<String, AnimalFacts>>>,
+}
+
+impl PrefetchedFacts {
+  pub fn new(capacity: usize, max_age: Duration) -> Self {
+    PrefetchedFacts {
+      capacity,
+      max_age,
+      facts: Arc::default(),
+    }
+  }
+
+  /// Keeping the extra facts of the animal up to the capacity, skipping the
+  /// ones kept already
+  pub fn push(&self, animal: &str, extras: impl IntoIterator<Item = Fact>) {
+    if self.capacity == 0 || self.max_age.is_zero() {
+      return;
+    }
+    let mut facts = self.facts.lock().unwrap();
+    let prefetched = facts.entry(animal.to_string()).or_default();
+    let fetched_at = Instant::now();
+    for fact in extras {
+      if prefetched.len() >= self.capacity {
+        break;
+      }
+      if prefetched.iter().all(|(_, kept)| kept.text != fact.text) {
+        prefetched.push_back((fetched_at, fact));
+      }
+    }
+  }
+
+  /// The oldest prefetched fact of the animal not older than the max age
+  pub fn pop(&self, animal: &str) -> Option<Fact> {
+    let mut facts = self.facts.lock().unwrap();
+    let prefetched = facts.get_mut(animal)?;
+    while let Some((fetched_at, fact)) = prefetched.pop_front() {
+      if fetched_at.elapsed() <= self.max_age {
+        return Some(fact);
+      }
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Facts are served in the order they were fetched up to the capacity,
+  /// without the duplicates
+  #[test]
+  fn capacity() {
+    let prefetched = PrefetchedFacts::new(2, Duration::from_secs(60));
+    prefetched
+      .push("dog", ["first", "first", "second", "third"].map(Fact::new));
+
+    assert_eq!(prefetched.pop("dog").unwrap().text, "first");
+    assert_eq!(prefetched.pop("dog").unwrap().text, "second");
+    assert!(prefetched.pop("dog").is_none());
+    assert!(prefetched.pop("cat").is_none());
+  }
+
+  /// Facts older than the max age should not be served
+  #[test]
+  fn max_age() {
+    let prefetched = PrefetchedFacts::new(2, Duration::from_millis(1));
+    prefetched.push("dog", [Fact::new("some dog fact")]);
+    std::thread::sleep(Duration::from_millis(5));
+
+    assert!(prefetched.pop("dog").is_none());
+  }
+}
@@ -0,0 +1,142 @@
+//! Recently resolved facts cache
+//!
+//! Remembers the last facts of every animal with the time they were resolved,
+//! so the resolver can serve a recently seen fact marked as stale when the
+//! endpoint request fails, instead of returning an error. Also tracks the
+//! background refreshes in flight, so a failing endpoint is refreshed by a
+//! single task at a time.
+
+use super::Fact;
+use rand::seq::SliceRandom;
+use std::{
+  collections::{HashMap, HashSet, VecDeque},
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+/// Recent facts of an animal with the time they were resolved, oldest first
+type AnimalFacts = VecDeque<(Instant, Fact)>;
+
+/// Recent facts of the animals shared between the resolver clones
+#[derive(Debug, Clone)]
+pub struct RecentFacts {
+  capacity: usize,
+  max_staleness: Duration,
+  facts: Arc<Mutex<HashMap<String, AnimalFacts>>>,
+  refreshing: Arc<Mutex<HashSet<String>>>,
+}
+
+impl RecentFacts {
+  pub fn new(capacity: usize, max_staleness: Duration) -> Self {
+    RecentFacts {
+      capacity,
+      max_staleness,
+      facts: Arc::default(),
+      refreshing: Arc::default(),
+    }
+  }
+
+  /// Remembering the freshly resolved fact, forgetting the oldest one when
+  /// the capacity is reached
+  pub fn remember(&self, animal: &str, fact: &Fact) {
+    if self.capacity == 0 {
+      return;
+    }
+    let mut facts = self.facts.lock().unwrap();
+    let recent = facts.entry(animal.to_string()).or_default();
+    recent.retain(|(_, recent_fact)| recent_fact.text != fact.text);
+    if recent.len() >= self.capacity {
+      recent.pop_front();
+    }
+    recent.push_back((Instant::now(), fact.clone()));
+  }
+
+  /// Distinct random recent facts of the animal not older than the max
+  /// staleness, up to the count
+  pub fn recent(&self, animal: &str, count: usize) -> Vec<Fact> {
+    if self.max_staleness.is_zero() {
+      return Vec::new();
+    }
+    let mut facts = self.facts.lock().unwrap();
+    let Some(recent) = facts.get_mut(animal) else {
+      return Vec::new();
+    };
+    recent
+      .retain(|(resolved_at, _)| resolved_at.elapsed() <= self.max_staleness);
+    recent
+      .make_contiguous()
+      .choose_multiple(&mut rand::thread_rng(), count)
+      .map(|(_, fact)| fact.clone())
+      .collect()
+  }
+
+  /// Marking the animal refresh as started. Returns `false` when there is a
+  /// refresh of the animal in flight already.
+  pub fn start_refresh(&self, animal: &str) -> bool {
+    self.refreshing.lock().unwrap().insert(animal.to_string())
+  }
+
+  /// Marking the animal refresh as finished
+  pub fn finish_refresh(&self, animal: &str) {
+    self.refreshing.lock().unwrap().remove(animal);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Only the last facts up to the capacity should be remembered
+  #[test]
+  fn capacity() {
+    let recent_facts = RecentFacts::new(2, Duration::from_secs(60));
+    for fact in ["first", "second", "third", "third"] {
+      recent_facts.remember("dog", &Fact::new(fact));
+    }
+
+    for _n in 1..10 {
+      let fact = recent_facts.recent("dog", 1).pop().unwrap().text;
+      assert!(fact == "second" || fact == "third", "unexpected {}", fact);
+    }
+    assert!(recent_facts.recent("cat", 1).is_empty());
+  }
+
+  /// Facts older than the max staleness should not be served
+  #[test]
+  fn max_staleness() {
+    let recent_facts = RecentFacts::new(2, Duration::from_millis(1));
+    recent_facts.remember("dog", &Fact::new("some dog fact"));
+    std::thread::sleep(Duration::from_millis(5));
+
+    assert!(recent_facts.recent("dog", 1).is_empty());
+  }
+
+  /// Several recent facts should be distinct, as many as remembered at most
+  #[test]
+  fn distinct() {
+    let recent_facts = RecentFacts::new(3, Duration::from_secs(60));
+    for fact in ["first", "second", "third"] {
+      recent_facts.remember("dog", &Fact::new(fact));
+    }
+
+    let mut facts: Vec<String> = recent_facts
+      .recent("dog", 5)
+      .into_iter()
+      .map(|fact| fact.text)
+      .collect();
+    facts.sort();
+    assert_eq!(facts, ["first", "second", "third"]);
+    assert_eq!(recent_facts.recent("dog", 2).len(), 2);
+  }
+
+  /// Only one refresh of the animal should be in flight
+  #[test]
+  fn single_refresh() {
+    let recent_facts = RecentFacts::new(2, Duration::from_secs(60));
+
+    assert!(recent_facts.start_refresh("dog"));
+    assert!(!recent_facts.start_refresh("dog"));
+    recent_facts.finish_refresh("dog");
+    assert!(recent_facts.start_refresh("dog"));
+  }
+}
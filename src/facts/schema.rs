@@ -0,0 +1,204 @@
+//! Endpoint response schemas
+//!
+//! Sources can point to the JSON Schema file their successful endpoint
+//! responses are validated against before the adapter transforms them. The
+//! violations are logged with the path of the offending value, counted and fail
+//! the source with the `ContractBroken` error, so the endpoint API drift is
+//! noticed before the adapters start failing or returning wrong facts.
+
+use super::ResponseContext;
+use anyhow::anyhow;
+use jsonschema::Validator;
+use serde_json::Value;
+use std::{
+  collections::HashMap,
+  fmt,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+};
+use tracing::{info, warn};
+
+/// Error of the endpoint response violating the source schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractBroken {
+  pub url: String,
+  /// Violations prefixed by the JSON pointer of the offending value
+  pub violations: Vec<String>,
+}
+
+impl fmt::Display for ContractBroken {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "upstream contract broken by {}: {}",
+      self.url,
+      self.violations.join("; ")
+    )
+  }
+}
+
+impl std::error::Error for ContractBroken {}
+
+/// Compiled source schemas by their file path and the violations counter shared
+/// between the resolver clones
+#[derive(Clone, Default)]
+pub struct SchemaValidator {
+  schemas: Arc<Mutex<HashMap<String, Arc<Validator>>>>,
+  violations: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for SchemaValidator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SchemaValidator")
+      .field("violations", &self.violations)
+      .finish_non_exhaustive()
+  }
+}
+
+impl SchemaValidator {
+  /// Validating the endpoint response against the schema file. Unsuccessful
+  /// responses are left to the adapters reporting their status.
+  pub async fn validate(
+    &self,
+    path: &str,
+    response: &ResponseContext,
+  ) -> anyhow::Result<()> {
+    if !response.status.is_success() {
+      return Ok(());
+    }
+    let schema = self.schema(path).await?;
+    let violations: Vec<String> =
+      match serde_json::from_slice::<Value>(&response.body) {
+        Ok(payload) => schema
+          .iter_errors(&payload)
+          .map(|err| {
+            let pointer = err.instance_path.as_str();
+            let pointer = if pointer.is_empty() { "/" } else { pointer };
+            format!("{}: {}", pointer, err)
+          })
+          .collect(),
+        Err(err) => vec![format!("/: body is not JSON: {}", err)],
+      };
+    if violations.is_empty() {
+      return Ok(());
+    }
+
+    for violation in &violations {
+      warn!(
+        url = response.url,
+        "upstream schema violation at {}", violation
+      );
+    }
+    let count = violations.len() as u64;
+    let total = self.violations.fetch_add(count, Ordering::Relaxed) + count;
+    info!(
+      url = response.url,
+      broken = count,
+      violations = total,
+      "upstream contract broken"
+    );
+    Err(
+      ContractBroken {
+        url: response.url.clone(),
+        violations,
+      }
+      .into(),
+    )
+  }
+
+  /// Number of the schema violations of all the responses
+  #[cfg(test)]
+  pub fn violations(&self) -> u64 {
+    self.violations.load(Ordering::Relaxed)
+  }
+
+  /// Compiled schema of the file, read once
+  async fn schema(&self, path: &str) -> anyhow::Result<Arc<Validator>> {
+    if let Some(schema) = self.schemas.lock().unwrap().get(path) {
+      return Ok(schema.clone());
+    }
+    let file = path.to_string();
+    let schema = tokio::task::spawn_blocking(move || {
+      let text = std::fs::read_to_string(&file).map_err(|err| {
+        anyhow!("schema file {} reading error: {}", file, err)
+      })?;
+      let schema: Value = serde_json::from_str(&text)?;
+      jsonschema::validator_for(&schema)
+        .map_err(|err| anyhow!("invalid schema {}: {}", file, err))
+    })
+    .await??;
+    let schema = Arc::new(schema);
+    self
+      .schemas
+      .lock()
+      .unwrap()
+      .insert(path.to_string(), schema.clone());
+    Ok(schema)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use reqwest::StatusCode;
+  use std::io::Write;
+
+  /// Schema file requiring the list of the fact strings
+  fn schema_file() -> tempfile::NamedTempFile {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file
+      .write_all(
+        br#"{
+          "type": "object",
+          "required": ["facts"],
+          "properties": {
+            "facts": { "type": "array", "items": { "type": "string" } }
+          }
+        }"#,
+      )
+      .unwrap();
+    file
+  }
+
+  /// Valid and unsuccessful responses pass the validation
+  #[tokio::test]
+  async fn validating_valid() {
+    let file = schema_file();
+    let path = file.path().to_str().unwrap();
+    let validator = SchemaValidator::default();
+
+    let response = ResponseContext::new("/facts", r#"{"facts": ["a fact"]}"#);
+    validator.validate(path, &response).await.unwrap();
+    let mut response = ResponseContext::new("/facts", "Too Many Requests");
+    response.status = StatusCode::TOO_MANY_REQUESTS;
+    validator.validate(path, &response).await.unwrap();
+    assert_eq!(validator.violations(), 0);
+  }
+
+  /// Violations are reported with the offending path and counted
+  #[tokio::test]
+  async fn validating_invalid() {
+    let file = schema_file();
+    let path = file.path().to_str().unwrap();
+    let validator = SchemaValidator::default();
+
+    let response = ResponseContext::new("/facts", r#"{"facts": ["a", 1, 2]}"#);
+    let err = validator.validate(path, &response).await.unwrap_err();
+    let broken = err.downcast_ref::<ContractBroken>().unwrap();
+    assert_eq!(broken.violations.len(), 2);
+    assert!(broken.violations[0].starts_with("/facts/1: "));
+
+    let response = ResponseContext::new("/facts", "not a json");
+    let err = validator.validate(path, &response).await.unwrap_err();
+    assert!(err.downcast_ref::<ContractBroken>().is_some());
+    assert_eq!(validator.violations(), 3);
+
+    let err = validator
+      .validate("/nonexistent/schema.json", &response)
+      .await
+      .unwrap_err();
+    assert!(err.downcast_ref::<ContractBroken>().is_none());
+  }
+}
@@ -0,0 +1,108 @@
+//! Facts seen by the clients
+//!
+//! Remembers the last facts returned to every client, so the resolver can
+//! re-fetch instead of returning a fact the client has seen recently. The
+//! number of the remembered clients is bounded, the clients seen first are
+//! forgotten first.
+
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{Arc, Mutex},
+};
+
+/// Last facts of the clients with the order the clients were added in
+#[derive(Debug, Default)]
+struct Clients {
+  facts: HashMap<String, VecDeque<String>>,
+  order: VecDeque<String>,
+}
+
+/// Seen facts of the clients shared between the resolver clones
+#[derive(Debug, Clone)]
+pub struct SeenFacts {
+  history: usize,
+  max_clients: usize,
+  clients: Arc<Mutex<Clients>>,
+}
+
+impl SeenFacts {
+  pub fn new(history: usize, max_clients: usize) -> Self {
+    SeenFacts {
+      history,
+      max_clients,
+      clients: Arc::default(),
+    }
+  }
+
+  /// The suppression is enabled by the non-zero history
+  pub fn enabled(&self) -> bool {
+    self.history > 0 && self.max_clients > 0
+  }
+
+  /// Whether the fact is one of the last facts returned to the client
+  pub fn contains(&self, client: &str, fact: &str) -> bool {
+    let clients = self.clients.lock().unwrap();
+    clients
+      .facts
+      .get(client)
+      .is_some_and(|facts| facts.iter().any(|seen| seen == fact))
+  }
+
+  /// Remembering the fact returned to the client
+  pub fn record(&self, client: &str, fact: &str) {
+    if !self.enabled() {
+      return;
+    }
+    let mut clients = self.clients.lock().unwrap();
+    if !clients.facts.contains_key(client) {
+      if clients.order.len() >= self.max_clients {
+        if let Some(oldest) = clients.order.pop_front() {
+          clients.facts.remove(&oldest);
+        }
+      }
+      clients.order.push_back(client.to_string());
+    }
+    let facts = clients.facts.entry(client.to_string()).or_default();
+    facts.retain(|seen| seen != fact);
+    if facts.len() >= self.history {
+      facts.pop_front();
+    }
+    facts.push_back(fact.to_string());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Only the last facts of the client up to the history are remembered
+  #[test]
+  fn client_history() {
+    let seen = SeenFacts::new(2, 10);
+    for fact in ["first", "second", "third"] {
+      seen.record("alice", fact);
+    }
+
+    assert!(!seen.contains("alice", "first"));
+    assert!(seen.contains("alice", "second"));
+    assert!(seen.contains("alice", "third"));
+    assert!(!seen.contains("bob", "third"));
+  }
+
+  /// The clients added first are forgotten when the limit is reached
+  #[test]
+  fn max_clients() {
+    let seen = SeenFacts::new(2, 2);
+    for client in ["alice", "bob", "carol"] {
+      seen.record(client, "fact");
+    }
+
+    assert!(!seen.contains("alice", "fact"));
+    assert!(seen.contains("bob", "fact"));
+    assert!(seen.contains("carol", "fact"));
+
+    let disabled = SeenFacts::new(0, 2);
+    disabled.record("alice", "fact");
+    assert!(!disabled.contains("alice", "fact"));
+  }
+}
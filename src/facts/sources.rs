@@ -0,0 +1,189 @@
+//! Fact sources balancing
+//!
+//! Every animal can be served by multiple sources. The balancer decides the
+//! order the sources are tried in by the configured strategy and tracks the
+//! observed latency of every source for the `fastest` strategy. Failed requests
+//! are counted with the penalty latency, so a failing source drops behind the
+//! healthy ones until it responds quickly again.
+
+use super::config::{SourceConfig, SourcesConfig, Strategy};
+use rand::Rng;
+use std::{
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+  time::Duration,
+};
+
+/// Latency recorded for the failed source requests
+const FAILURE_PENALTY: Duration = Duration::from_secs(10);
+
+/// Weight of the latest latency in the moving average
+const LATENCY_SMOOTHING: f64 = 0.3;
+
+/// Sources of an animal with the selection state shared between the resolver
+/// clones
+#[derive(Debug, Clone)]
+pub struct SourceBalancer {
+  strategy: Strategy,
+  sources: Vec<SourceConfig>,
+  next: Arc<AtomicUsize>,
+  latencies: Arc<Mutex<Vec<Option<Duration>>>>,
+}
+
+impl SourceBalancer {
+  pub fn new(config: SourcesConfig) -> Self {
+    SourceBalancer {
+      strategy: config.strategy,
+      latencies: Arc::new(Mutex::new(vec![None; config.sources.len()])),
+      sources: config.sources,
+      next: Arc::default(),
+    }
+  }
+
+  /// Source by the index returned from `order`
+  pub fn source(&self, index: usize) -> &SourceConfig {
+    &self.sources[index]
+  }
+
+  /// Indexes of the sources in the order they should be tried
+  pub fn order(&self) -> Vec<usize> {
+    let count = self.sources.len();
+    match self.strategy {
+      Strategy::Failover => (0..count).collect(),
+      Strategy::RoundRobin => {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..count).map(|offset| (start + offset) % count).collect()
+      }
+      Strategy::WeightedRandom => self.weighted_order(&mut rand::thread_rng()),
+      Strategy::Fastest => {
+        let latencies = self.latencies.lock().unwrap();
+        let mut order: Vec<usize> = (0..count).collect();
+        // Sources without the observed latency go first to get measured
+        order.sort_by_key(|index| latencies[*index].unwrap_or_default());
+        order
+      }
+    }
+  }
+
+  /// Random order where the source chance to be tried earlier is proportional
+  /// to its weight. Sources with the zero weight are tried last.
+  fn weighted_order(&self, rng: &mut impl Rng) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..self.sources.len()).collect();
+    let mut order = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+      let total: u64 = remaining
+        .iter()
+        .map(|index| u64::from(self.sources[*index].weight))
+        .sum();
+      let position = if total == 0 {
+        0
+      } else {
+        let mut point = rng.gen_range(0..total);
+        remaining
+          .iter()
+          .position(|index| {
+            let weight = u64::from(self.sources[*index].weight);
+            if point < weight {
+              return true;
+            }
+            point -= weight;
+            false
+          })
+          .unwrap_or(0)
+      };
+      order.push(remaining.remove(position));
+    }
+    order
+  }
+
+  /// Recording the latency of the successful source request
+  pub fn record_success(&self, index: usize, latency: Duration) {
+    self.record_latency(index, latency);
+  }
+
+  /// Recording the failed source request with the penalty latency
+  pub fn record_failure(&self, index: usize) {
+    self.record_latency(index, FAILURE_PENALTY);
+  }
+
+  fn record_latency(&self, index: usize, latency: Duration) {
+    let mut latencies = self.latencies.lock().unwrap();
+    latencies[index] = Some(match latencies[index] {
+      Some(average) => {
+        average.mul_f64(1.0 - LATENCY_SMOOTHING)
+          + latency.mul_f64(LATENCY_SMOOTHING)
+      }
+      None => latency,
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{rngs::StdRng, SeedableRng};
+
+  fn balancer(strategy: Strategy, weights: &[u32]) -> SourceBalancer {
+    SourceBalancer::new(SourcesConfig {
+      strategy,
+      sources: weights
+        .iter()
+        .enumerate()
+        .map(|(index, weight)| SourceConfig {
+          url: format!("http://source-{}", index),
+          weight: *weight,
+          ..Default::default()
+        })
+        .collect(),
+    })
+  }
+
+  /// Failover tries the sources in order, round robin rotates the first one
+  #[test]
+  fn failover_and_round_robin() {
+    let failover = balancer(Strategy::Failover, &[1, 1, 1]);
+    assert_eq!(failover.order(), vec![0, 1, 2]);
+    assert_eq!(failover.order(), vec![0, 1, 2]);
+
+    let round_robin = balancer(Strategy::RoundRobin, &[1, 1, 1]);
+    assert_eq!(round_robin.order(), vec![0, 1, 2]);
+    assert_eq!(round_robin.order(), vec![1, 2, 0]);
+    assert_eq!(round_robin.clone().order(), vec![2, 0, 1]);
+  }
+
+  /// Heavier sources should be tried first more often, zero weights last
+  #[test]
+  fn weighted_random() {
+    let weighted = balancer(Strategy::WeightedRandom, &[1, 9, 0]);
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut heavier_first = 0;
+    for _n in 0..1000 {
+      let order = weighted.weighted_order(&mut rng);
+      assert_eq!(order.len(), 3);
+      assert_eq!(order[2], 2, "zero weight source is not the last");
+      if order[0] == 1 {
+        heavier_first += 1;
+      }
+    }
+    assert!(
+      heavier_first > 800,
+      "heavier source first {}",
+      heavier_first
+    );
+  }
+
+  /// Unmeasured sources go first, then the fastest ones, failures go last
+  #[test]
+  fn fastest() {
+    let fastest = balancer(Strategy::Fastest, &[1, 1, 1]);
+    fastest.record_success(0, Duration::from_millis(300));
+    fastest.record_success(1, Duration::from_millis(100));
+    assert_eq!(fastest.order(), vec![2, 1, 0]);
+
+    fastest.record_failure(2);
+    fastest.record_failure(1);
+    assert_eq!(fastest.order(), vec![0, 1, 2]);
+  }
+}
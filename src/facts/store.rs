@@ -0,0 +1,623 @@
+//! Local persistent fact store
+//!
+//! Every fact passing an adapter is persisted into the SQLite database with the
+//! source endpoint, fetch time and content hash. Facts are unique per animal by
+//! the content hash, so fetching the same fact again only refreshes its fetch
+//! time. The stored corpus is used to serve facts when the endpoints are down.
+//!
+//! The store also keeps the facts of the day pinned per animal and date, so the
+//! daily fact stays the same after the server restarts, and the FTS5 full-text
+//! index of the facts used by the search. The store can be capped, pruning the
+//! oldest facts.
+
+use anyhow::bail;
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use std::{
+  fmt,
+  path::Path,
+  str::FromStr,
+  sync::{Arc, Mutex},
+  time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{instrument, trace};
+
+/// Fact record of the store
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredFact {
+  pub animal: String,
+  pub text: String,
+  /// Endpoint the fact was fetched from
+  pub endpoint: String,
+  /// Fetch time in seconds since the Unix epoch
+  pub fetched_at: u64,
+  /// Hex encoded SHA-256 of the fact text
+  pub hash: String,
+}
+
+impl StoredFact {
+  /// Creating the record of the fact fetched right now
+  pub fn new(animal: &str, text: &str, endpoint: &str) -> Self {
+    StoredFact {
+      animal: animal.to_string(),
+      text: text.to_string(),
+      endpoint: endpoint.to_string(),
+      fetched_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default(),
+      hash: content_hash(text),
+    }
+  }
+}
+
+/// Hex encoded SHA-256 hash of the fact text
+pub fn content_hash(text: &str) -> String {
+  format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// Fact matching the search query
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+  pub animal: String,
+  pub text: String,
+  /// Fact text part around the matched terms, highlighted with `<mark>`
+  pub snippet: String,
+  /// BM25 relevance score, the lower the more relevant
+  pub score: f64,
+  /// Cursor of the next page starting after this hit
+  pub cursor: SearchCursor,
+}
+
+/// Position in the search results of the snapshot, the score and id of the
+/// last hit. The snapshot is the last fact id when the first page was searched,
+/// so the facts stored later do not shift the pages, and the next page starts
+/// after the last hit, so the pruned facts do not shift them either.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchCursor {
+  snapshot: i64,
+  score: f64,
+  id: i64,
+}
+
+/// Opaque hex encoding of the cursor
+impl fmt::Display for SearchCursor {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{:016x}{:016x}{:x}",
+      self.snapshot,
+      self.score.to_bits(),
+      self.id
+    )
+  }
+}
+
+impl FromStr for SearchCursor {
+  type Err = anyhow::Error;
+
+  fn from_str(cursor: &str) -> anyhow::Result<Self> {
+    let (snapshot, score, id) =
+      match (cursor.get(..16), cursor.get(16..32), cursor.get(32..)) {
+        (Some(snapshot), Some(score), Some(id)) if !id.is_empty() => {
+          (snapshot, score, id)
+        }
+        _ => bail!("invalid search cursor"),
+      };
+    Ok(SearchCursor {
+      snapshot: i64::from_str_radix(snapshot, 16)?,
+      score: f64::from_bits(u64::from_str_radix(score, 16)?),
+      id: i64::from_str_radix(id, 16)?,
+    })
+  }
+}
+
+/// FTS5 query matching all the alphanumeric terms of the user query
+fn match_query(query: &str) -> String {
+  query
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|term| !term.is_empty())
+    .map(|term| format!("\"{}\"", term))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// SQLite fact store shared between the resolver clones
+#[derive(Debug, Clone)]
+pub struct FactStore {
+  connection: Arc<Mutex<Connection>>,
+  /// Maximum number of the stored facts, unlimited when `None`
+  capacity: Option<usize>,
+}
+
+impl FactStore {
+  /// Opening the database file and creating the schema if needed
+  #[instrument]
+  pub fn open(
+    path: impl AsRef<Path> + std::fmt::Debug,
+  ) -> anyhow::Result<Self> {
+    trace!("opening the fact store");
+    Self::with_connection(Connection::open(path)?)
+  }
+
+  /// Opening the in-memory database, not persisted between the restarts
+  pub fn open_in_memory() -> anyhow::Result<Self> {
+    Self::with_connection(Connection::open_in_memory()?)
+  }
+
+  fn with_connection(connection: Connection) -> anyhow::Result<Self> {
+    let indexed: bool = connection.query_row(
+      "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'facts_search')",
+      [],
+      |row| row.get(0),
+    )?;
+    connection.execute_batch(
+      "CREATE TABLE IF NOT EXISTS facts (
+        id INTEGER PRIMARY KEY,
+        animal TEXT NOT NULL,
+        text TEXT NOT NULL,
+        endpoint TEXT NOT NULL,
+        fetched_at INTEGER NOT NULL,
+        hash TEXT NOT NULL,
+        UNIQUE (animal, hash)
+      );
+      CREATE INDEX IF NOT EXISTS facts_animal ON facts (animal);
+      CREATE TABLE IF NOT EXISTS daily_facts (
+        animal TEXT NOT NULL,
+        date TEXT NOT NULL,
+        text TEXT NOT NULL,
+        PRIMARY KEY (animal, date)
+      );
+      CREATE VIRTUAL TABLE IF NOT EXISTS facts_search
+        USING fts5(text, content = 'facts', content_rowid = 'id');
+      CREATE TRIGGER IF NOT EXISTS facts_search_insert AFTER INSERT ON facts
+      BEGIN
+        INSERT INTO facts_search (rowid, text) VALUES (new.id, new.text);
+      END;
+      CREATE TRIGGER IF NOT EXISTS facts_search_delete AFTER DELETE ON facts
+      BEGIN
+        INSERT INTO facts_search (facts_search, rowid, text)
+          VALUES ('delete', old.id, old.text);
+      END;",
+    )?;
+    if !indexed {
+      trace!("indexing the stored facts for the search");
+      connection.execute(
+        "INSERT INTO facts_search (facts_search) VALUES ('rebuild')",
+        [],
+      )?;
+    }
+    Ok(FactStore {
+      connection: Arc::new(Mutex::new(connection)),
+      capacity: None,
+    })
+  }
+
+  /// Capping the number of the stored facts, the oldest ones are pruned
+  pub fn with_capacity(mut self, capacity: usize) -> Self {
+    self.capacity = Some(capacity);
+    self
+  }
+
+  /// Persisting the fact, refreshing the fetch time and endpoint of the
+  /// already stored one
+  #[instrument(skip(self))]
+  pub fn insert(&self, fact: &StoredFact) -> anyhow::Result<()> {
+    trace!("persisting the fact");
+    let connection = self.connection.lock().unwrap();
+    connection.execute(
+      "INSERT INTO facts (animal, text, endpoint, fetched_at, hash)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT (animal, hash) DO UPDATE SET
+          endpoint = excluded.endpoint,
+          fetched_at = excluded.fetched_at",
+      params![
+        fact.animal,
+        fact.text,
+        fact.endpoint,
+        fact.fetched_at,
+        fact.hash
+      ],
+    )?;
+    if let Some(capacity) = self.capacity {
+      connection.execute(
+        "DELETE FROM facts WHERE id IN (
+          SELECT id FROM facts ORDER BY id
+          LIMIT max((SELECT COUNT(*) FROM facts) - ?1, 0)
+        )",
+        params![capacity as i64],
+      )?;
+    }
+    Ok(())
+  }
+
+  /// Random stored fact of the animal
+  #[instrument(skip(self))]
+  pub fn random(&self, animal: &str) -> anyhow::Result<Option<StoredFact>> {
+    trace!("selecting a random stored fact");
+    let connection = self.connection.lock().unwrap();
+    let fact = connection
+      .query_row(
+        "SELECT animal, text, endpoint, fetched_at, hash FROM facts
+          WHERE animal = ?1 ORDER BY RANDOM() LIMIT 1",
+        params![animal],
+        |row| {
+          Ok(StoredFact {
+            animal: row.get(0)?,
+            text: row.get(1)?,
+            endpoint: row.get(2)?,
+            fetched_at: row.get(3)?,
+            hash: row.get(4)?,
+          })
+        },
+      )
+      .optional()?;
+    Ok(fact)
+  }
+
+  /// Stored fact of the animal chosen deterministically for the date. The
+  /// choice depends only on the date and the stored facts.
+  #[instrument(skip(self))]
+  pub fn daily_candidate(
+    &self,
+    animal: &str,
+    date: &str,
+  ) -> anyhow::Result<Option<String>> {
+    trace!("choosing the daily fact from the stored facts");
+    let connection = self.connection.lock().unwrap();
+    let mut statement =
+      connection.prepare("SELECT text, hash FROM facts WHERE animal = ?1")?;
+    let facts = statement
+      .query_map(params![animal], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+      })?
+      .collect::<Result<Vec<_>, _>>()?;
+    let fact = facts
+      .into_iter()
+      .min_by_key(|(_, hash)| content_hash(&format!("{}:{}", date, hash)))
+      .map(|(text, _)| text);
+    Ok(fact)
+  }
+
+  /// Fact pinned for the animal and date
+  #[instrument(skip(self))]
+  pub fn pinned(
+    &self,
+    animal: &str,
+    date: &str,
+  ) -> anyhow::Result<Option<String>> {
+    let connection = self.connection.lock().unwrap();
+    let text = connection
+      .query_row(
+        "SELECT text FROM daily_facts WHERE animal = ?1 AND date = ?2",
+        params![animal, date],
+        |row| row.get(0),
+      )
+      .optional()?;
+    Ok(text)
+  }
+
+  /// Pinning the fact for the animal and date unless another fact is pinned
+  /// already. Returns the pinned fact.
+  #[instrument(skip(self))]
+  pub fn pin(
+    &self,
+    animal: &str,
+    date: &str,
+    text: &str,
+  ) -> anyhow::Result<String> {
+    trace!("pinning the daily fact");
+    let connection = self.connection.lock().unwrap();
+    connection.execute(
+      "INSERT OR IGNORE INTO daily_facts (animal, date, text)
+        VALUES (?1, ?2, ?3)",
+      params![animal, date, text],
+    )?;
+    let text = connection.query_row(
+      "SELECT text FROM daily_facts WHERE animal = ?1 AND date = ?2",
+      params![animal, date],
+      |row| row.get(0),
+    )?;
+    Ok(text)
+  }
+
+  /// Stored facts matching all the terms of the query, the most relevant
+  /// first, starting after the cursor. The results of the next pages are
+  /// limited to the snapshot of the first page and ordered by the score and
+  /// id, so the next page continues right after the last hit. The scores
+  /// change with the stored facts, so the last hit is scored again, and the
+  /// cursor score is used only when the hit was pruned since.
+  #[instrument(skip(self))]
+  pub fn search(
+    &self,
+    query: &str,
+    animal: Option<&str>,
+    after: Option<SearchCursor>,
+    limit: usize,
+  ) -> anyhow::Result<Vec<SearchHit>> {
+    trace!("searching the stored facts");
+    let query = match_query(query);
+    if query.is_empty() {
+      return Ok(Vec::new());
+    }
+    let connection = self.connection.lock().unwrap();
+    let cursor = match after {
+      Some(cursor) => cursor,
+      None => SearchCursor {
+        snapshot: connection.query_row(
+          "SELECT COALESCE(MAX(id), 0) FROM facts",
+          [],
+          |row| row.get(0),
+        )?,
+        score: f64::NEG_INFINITY,
+        id: 0,
+      },
+    };
+    let mut statement = connection.prepare(
+      "WITH hits AS (
+        SELECT facts.animal, facts.text,
+          snippet(facts_search, 0, '<mark>', '</mark>', '…', 16) AS snippet,
+          bm25(facts_search) AS score, facts.id
+        FROM facts_search JOIN facts ON facts.id = facts_search.rowid
+        WHERE facts_search MATCH ?1 AND (?2 IS NULL OR facts.animal = ?2)
+          AND facts.id <= ?3
+      ),
+      last AS (
+        SELECT COALESCE((SELECT score FROM hits WHERE id = ?5), ?4) AS score
+      )
+      SELECT hits.animal, hits.text, hits.snippet, hits.score, hits.id
+      FROM hits, last
+      WHERE hits.score > last.score
+        OR (hits.score = last.score AND hits.id > ?5)
+      ORDER BY hits.score, hits.id
+      LIMIT ?6",
+    )?;
+    let hits = statement
+      .query_map(
+        params![
+          query,
+          animal,
+          cursor.snapshot,
+          cursor.score,
+          cursor.id,
+          limit as i64
+        ],
+        |row| {
+          let score = row.get(3)?;
+          Ok(SearchHit {
+            animal: row.get(0)?,
+            text: row.get(1)?,
+            snippet: row.get(2)?,
+            score,
+            cursor: SearchCursor {
+              snapshot: cursor.snapshot,
+              score,
+              id: row.get(4)?,
+            },
+          })
+        },
+      )?
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(hits)
+  }
+
+  /// Number of the stored facts of the animal
+  #[cfg(test)]
+  pub fn count(&self, animal: &str) -> anyhow::Result<u64> {
+    let connection = self.connection.lock().unwrap();
+    let count = connection.query_row(
+      "SELECT COUNT(*) FROM facts WHERE animal = ?1",
+      params![animal],
+      |row| row.get(0),
+    )?;
+    Ok(count)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  /// Same facts should be stored once per animal
+  #[test]
+  fn insert_deduplicates_by_hash() {
+    let store = FactStore::open_in_memory().unwrap();
+    let fact = StoredFact::new("dog", "some dog fact", "http://first");
+
+    store.insert(&fact).unwrap();
+    store
+      .insert(&StoredFact::new("dog", "some dog fact", "http://second"))
+      .unwrap();
+    store
+      .insert(&StoredFact::new("cat", "some dog fact", "http://first"))
+      .unwrap();
+
+    assert_eq!(store.count("dog").unwrap(), 1);
+    assert_eq!(store.count("cat").unwrap(), 1);
+    let stored = store.random("dog").unwrap().unwrap();
+    assert_eq!(
+      stored.endpoint, "http://second",
+      "endpoint is not refreshed"
+    );
+    assert_eq!(stored.hash, fact.hash);
+  }
+
+  /// Random fact should be selected only from the animal facts
+  #[test]
+  fn random_fact_of_animal() {
+    let store = FactStore::open_in_memory().unwrap();
+    assert!(store.random("dog").unwrap().is_none());
+
+    store
+      .insert(&StoredFact::new("cat", "some cat fact", "http://cat"))
+      .unwrap();
+    let stored = store.random("cat").unwrap().unwrap();
+
+    assert_eq!(stored.text, "some cat fact");
+    assert!(store.random("dog").unwrap().is_none());
+  }
+
+  /// Daily facts are chosen deterministically and pinned once per date
+  #[test]
+  fn daily_pins() {
+    let store = FactStore::open_in_memory().unwrap();
+    assert!(store
+      .daily_candidate("dog", "2024-01-01")
+      .unwrap()
+      .is_none());
+    for text in ["first fact", "second fact", "third fact"] {
+      store
+        .insert(&StoredFact::new("dog", text, "http://dog"))
+        .unwrap();
+    }
+    let candidate = store.daily_candidate("dog", "2024-01-01").unwrap();
+    for _n in 0..5 {
+      assert_eq!(
+        store.daily_candidate("dog", "2024-01-01").unwrap(),
+        candidate
+      );
+    }
+
+    assert!(store.pinned("dog", "2024-01-01").unwrap().is_none());
+    assert_eq!(
+      store.pin("dog", "2024-01-01", "first fact").unwrap(),
+      "first fact"
+    );
+    assert_eq!(
+      store.pin("dog", "2024-01-01", "third fact").unwrap(),
+      "first fact"
+    );
+    assert_eq!(
+      store.pinned("dog", "2024-01-01").unwrap().as_deref(),
+      Some("first fact")
+    );
+    assert!(store.pinned("dog", "2024-01-02").unwrap().is_none());
+    assert!(store.pinned("cat", "2024-01-01").unwrap().is_none());
+  }
+
+  /// Search matches all the terms, ranks, highlights and pages the results
+  #[test]
+  fn full_text_search() {
+    let store = FactStore::open_in_memory().unwrap();
+    for (animal, text) in [
+      ("dog", "Dogs have a great sense of smell"),
+      ("dog", "A dog nose print is unique, dog owners say"),
+      ("cat", "Cats sleep most of the day"),
+      ("cat", "A cat nose print is unique as well"),
+    ] {
+      store
+        .insert(&StoredFact::new(animal, text, "http://facts"))
+        .unwrap();
+    }
+
+    let hits = store.search("nose, print!", None, None, 10).unwrap();
+    assert_eq!(hits.len(), 2);
+    assert!(hits[0].score <= hits[1].score, "hits are not ranked");
+    assert!(
+      hits[0].snippet.contains("<mark>nose</mark>"),
+      "{}",
+      hits[0].snippet
+    );
+
+    let hits = store.search("NOSE", Some("cat"), None, 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].animal, "cat");
+    assert!(store
+      .search("nose tail", None, None, 10)
+      .unwrap()
+      .is_empty());
+    assert!(store.search("\"*", None, None, 10).unwrap().is_empty());
+
+    // Paging through the results one by one
+    let mut cursor = None;
+    let mut texts = Vec::new();
+    while let Some(hit) = store.search("print", None, cursor, 1).unwrap().pop()
+    {
+      let encoded = hit.cursor.to_string();
+      cursor = Some(encoded.parse().unwrap());
+      texts.push(hit.text);
+    }
+    assert_eq!(texts.len(), 2);
+    assert_ne!(texts[0], texts[1]);
+    assert!("nothex".parse::<SearchCursor>().is_err());
+
+    // Facts stored after the first page do not shift the next pages
+    let first = store.search("print", None, None, 1).unwrap().pop().unwrap();
+    for n in 0..5 {
+      store
+        .insert(&StoredFact::new(
+          "dog",
+          &format!("print {} print print", n),
+          "http://facts",
+        ))
+        .unwrap();
+    }
+    let rest = store.search("print", None, Some(first.cursor), 10).unwrap();
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].text, texts[1]);
+  }
+
+  /// Capped store prunes the oldest facts from the facts and the search index
+  #[test]
+  fn capped_store() {
+    let store = FactStore::open_in_memory().unwrap().with_capacity(2);
+    for text in ["first fact", "second fact", "third fact"] {
+      store
+        .insert(&StoredFact::new("dog", text, "http://dog"))
+        .unwrap();
+    }
+    assert_eq!(store.count("dog").unwrap(), 2);
+    assert!(store.search("first", None, None, 10).unwrap().is_empty());
+    assert_eq!(store.search("fact", None, None, 10).unwrap().len(), 2);
+  }
+
+  /// Pruning the facts of the previous pages should not skip the next hits
+  #[test]
+  fn pruned_between_pages() {
+    let store = FactStore::open_in_memory().unwrap().with_capacity(3);
+    for text in [
+      "print",
+      "print of a nose",
+      "print of a nose is unique for every dog",
+    ] {
+      store
+        .insert(&StoredFact::new("dog", text, "http://dog"))
+        .unwrap();
+    }
+    let first = store.search("print", None, None, 1).unwrap().pop().unwrap();
+    assert_eq!(first.text, "print");
+
+    // Pruning the oldest fact, the one of the first page
+    store
+      .insert(&StoredFact::new("dog", "unrelated fact", "http://dog"))
+      .unwrap();
+    let rest = store.search("print", None, Some(first.cursor), 10).unwrap();
+    let texts: Vec<_> = rest.iter().map(|hit| hit.text.as_str()).collect();
+    assert_eq!(
+      texts,
+      ["print of a nose", "print of a nose is unique for every dog"]
+    );
+  }
+
+  /// Stored and pinned facts should survive reopening the database file
+  #[test]
+  fn persisted_in_file() {
+    let file = NamedTempFile::new().unwrap();
+    FactStore::open(file.path())
+      .unwrap()
+      .insert(&StoredFact::new("dog", "some dog fact", "http://dog"))
+      .unwrap();
+    FactStore::open(file.path())
+      .unwrap()
+      .pin("dog", "2024-01-01", "some dog fact")
+      .unwrap();
+
+    let store = FactStore::open(file.path()).unwrap();
+    assert_eq!(store.count("dog").unwrap(), 1);
+    assert_eq!(
+      store.pinned("dog", "2024-01-01").unwrap().as_deref(),
+      Some("some dog fact")
+    );
+  }
+}
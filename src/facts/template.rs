@@ -0,0 +1,334 @@
+//! Endpoint request templates
+//!
+//! The URL, header values, query values and body of a source are templates.
+//! `${NAME}` is replaced by the `NAME` environment variable, so the secrets
+//! like API keys stay out of the configuration file, and `{{name}}` by the
+//! request variable: `animal`, `count` of the requested facts or `language`.
+//! The count and language are the ones of the request, or the configured
+//! prefetch batch and language.
+
+use super::auth::Auth;
+use super::config::SourceConfig;
+use anyhow::{anyhow, bail};
+use reqwest::{Client, Method, RequestBuilder};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt};
+
+/// Printed instead of the values that may contain the secrets
+const REDACTED: &str = "<redacted>";
+
+/// Lookup of the environment variables by the name
+pub type EnvLookup = fn(&str) -> Option<String>;
+
+/// Environment variable of the process
+pub fn process_env(name: &str) -> Option<String> {
+  std::env::var(name).ok()
+}
+
+/// Values of the request variables and the environment variables lookup
+#[derive(Debug, Clone, Copy)]
+pub struct TemplateVars<'a> {
+  pub animal: &'a str,
+  pub count: usize,
+  pub language: Option<&'a str>,
+  pub env: EnvLookup,
+}
+
+/// Endpoint request rendered from the source templates
+#[derive(Clone)]
+pub struct EndpointRequest {
+  pub method: Method,
+  pub url: String,
+  pub headers: Vec<(String, String)>,
+  pub query: Vec<(String, String)>,
+  pub body: Option<String>,
+  pub auth: Option<Auth>,
+}
+
+/// Header values and the query may contain the secrets, so only the URL
+/// without the query and the redacted query pairs are printed
+impl fmt::Debug for EndpointRequest {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let query: Vec<(&str, &str)> =
+      self.query_names().map(|name| (name, REDACTED)).collect();
+    f.debug_struct("EndpointRequest")
+      .field("method", &self.method)
+      .field("url", &self.base_url())
+      .field("query", &query)
+      .field("auth", &self.auth)
+      .finish_non_exhaustive()
+  }
+}
+
+impl EndpointRequest {
+  /// Rendering the request templates of the source
+  pub fn render(
+    source: &SourceConfig,
+    vars: &TemplateVars,
+  ) -> anyhow::Result<Self> {
+    let method = match &source.method {
+      Some(method) => Method::from_bytes(method.to_uppercase().as_bytes())
+        .map_err(|_| anyhow!("invalid request method: {}", method))?,
+      None => Method::GET,
+    };
+    let render_pairs = |pairs: &HashMap<String, String>| {
+      let mut pairs = pairs
+        .iter()
+        .map(|(name, value)| Ok((name.clone(), render(value, vars)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+      // Sorted, so the same requests are coalesced whatever the map order is
+      pairs.sort();
+      anyhow::Ok(pairs)
+    };
+    Ok(EndpointRequest {
+      method,
+      url: render(&source.url, vars)?,
+      headers: render_pairs(&source.headers)?,
+      query: render_pairs(&source.query)?,
+      body: source
+        .body
+        .as_deref()
+        .map(|body| render(body, vars))
+        .transpose()?,
+      auth: source
+        .auth
+        .as_ref()
+        .map(|auth| Auth::render(auth, vars))
+        .transpose()?,
+    })
+  }
+
+  /// Building the request without the authentication
+  pub fn builder(&self, http: &Client) -> RequestBuilder {
+    let mut request = http
+      .request(self.method.clone(), &self.url)
+      .query(&self.query);
+    for (name, value) in &self.headers {
+      request = request.header(name, value);
+    }
+    if let Some(body) = &self.body {
+      request = request.body(body.clone());
+    }
+    request
+  }
+
+  /// Key of the requests sharing the response when coalesced. The requests
+  /// differing by any header or credential don't share the response, and the
+  /// key is hashed to keep the secrets of the headers, the query and the
+  /// credentials out of it.
+  pub fn key(&self) -> String {
+    let pairs = |pairs: &[(String, String)]| {
+      pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+    };
+    let mut hasher = Sha256::new();
+    for part in [
+      self.method.as_str(),
+      &self.url,
+      &pairs(&self.query),
+      &pairs(&self.headers),
+      self.body.as_deref().unwrap_or(""),
+      &self.auth.as_ref().map(Auth::identity).unwrap_or_default(),
+    ] {
+      // Length prefixes keep the parts from running into each other
+      hasher.update((part.len() as u64).to_be_bytes());
+      hasher.update(part.as_bytes());
+    }
+    format!(
+      "{} {} {:x}",
+      self.method,
+      self.base_url(),
+      hasher.finalize()
+    )
+  }
+
+  /// Request printable in the logs: the method and the URL without the query
+  /// followed by the names of the query parameters with the redacted values
+  pub fn redacted(&self) -> String {
+    let query: Vec<String> = self
+      .query_names()
+      .map(|name| format!("{}={}", name, REDACTED))
+      .collect();
+    match query.is_empty() {
+      true => format!("{} {}", self.method, self.base_url()),
+      false => {
+        format!("{} {}?{}", self.method, self.base_url(), query.join("&"))
+      }
+    }
+  }
+
+  /// URL of the request without its query string and fragment
+  fn base_url(&self) -> &str {
+    self.url.split(['?', '#']).next().unwrap_or_default()
+  }
+
+  /// Names of the query parameters of the URL and of the configured query
+  fn query_names(&self) -> impl Iterator<Item = &str> {
+    let url_query = self
+      .url
+      .split('#')
+      .next()
+      .and_then(|url| url.split_once('?'))
+      .map_or("", |(_, query)| query);
+    url_query
+      .split('&')
+      .filter(|pair| !pair.is_empty())
+      .map(|pair| pair.split_once('=').map_or(pair, |(name, _)| name))
+      .chain(self.query.iter().map(|(name, _)| name.as_str()))
+  }
+}
+
+/// Replacing the environment and request variables of the template
+pub fn render(template: &str, vars: &TemplateVars) -> anyhow::Result<String> {
+  let mut rendered = String::with_capacity(template.len());
+  let mut rest = template;
+  loop {
+    let env = rest.find("${");
+    let var = rest.find("{{");
+    let (start, open, close) = match (env, var) {
+      (Some(env), Some(var)) if var < env => (var, "{{", "}}"),
+      (Some(env), _) => (env, "${", "}"),
+      (None, Some(var)) => (var, "{{", "}}"),
+      (None, None) => break,
+    };
+    rendered.push_str(&rest[..start]);
+    let after = &rest[start + open.len()..];
+    let end = after.find(close).ok_or_else(|| {
+      anyhow!("unclosed `{}` in the template: {}", open, template)
+    })?;
+    let name = after[..end].trim();
+    match open {
+      "${" => rendered.push_str(&(vars.env)(name).ok_or_else(|| {
+        anyhow!("environment variable of the template is not set: {}", name)
+      })?),
+      _ => match name {
+        "animal" => rendered.push_str(vars.animal),
+        "count" => rendered.push_str(&vars.count.to_string()),
+        "language" => rendered.push_str(vars.language.unwrap_or("")),
+        _ => bail!("unknown template variable: {}", name),
+      },
+    }
+    rest = &after[end + close.len()..];
+  }
+  rendered.push_str(rest);
+  Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Environment of the tests with the `TEMPLATE_TEST_KEY` variable only
+  fn test_env(name: &str) -> Option<String> {
+    (name == "TEMPLATE_TEST_KEY").then(|| "secret".to_string())
+  }
+
+  const VARS: TemplateVars = TemplateVars {
+    animal: "dog",
+    count: 5,
+    language: Some("de"),
+    env: test_env,
+  };
+
+  /// Environment and request variables are replaced
+  #[test]
+  fn rendering_variables() {
+    assert_eq!(
+      render("Bearer ${TEMPLATE_TEST_KEY}", &VARS).unwrap(),
+      "Bearer secret"
+    );
+    assert_eq!(
+      render(
+        r#"{"animal":"{{animal}}","n":{{ count }},"l":"{{language}}"}"#,
+        &VARS
+      )
+      .unwrap(),
+      r#"{"animal":"dog","n":5,"l":"de"}"#
+    );
+    assert_eq!(
+      render("{not a variable}", &VARS).unwrap(),
+      "{not a variable}"
+    );
+  }
+
+  /// Requests differing by the headers or the credentials have different keys
+  /// without the secrets of the headers and the query
+  #[test]
+  fn request_keys() {
+    let source = SourceConfig {
+      url: "http://facts/{{animal}}?key=${TEMPLATE_TEST_KEY}".to_string(),
+      headers: HashMap::from([(
+        "X-Api-Key".to_string(),
+        "${TEMPLATE_TEST_KEY}".to_string(),
+      )]),
+      query: HashMap::from([(
+        "token".to_string(),
+        "${TEMPLATE_TEST_KEY}".to_string(),
+      )]),
+      ..Default::default()
+    };
+    let request = EndpointRequest::render(&source, &VARS).unwrap();
+    assert_eq!(request.key(), request.clone().key());
+    assert!(!request.key().contains("secret"), "{}", request.key());
+    assert!(request.key().starts_with("GET http://facts/dog "));
+
+    let mut other_header = request.clone();
+    other_header.headers[0].1 = "other".to_string();
+    let mut authenticated = request.clone();
+    authenticated.auth = Some(Auth::Basic {
+      username: "user".to_string(),
+      password: "password".to_string(),
+    });
+    let mut other_user = authenticated.clone();
+    other_user.auth = Some(Auth::Basic {
+      username: "other".to_string(),
+      password: "password".to_string(),
+    });
+    let keys = [request, other_header, authenticated, other_user]
+      .map(|request| request.key());
+    for (n, key) in keys.iter().enumerate() {
+      assert!(!keys[n + 1..].contains(key), "{}", key);
+    }
+  }
+
+  /// Printed requests have the query values redacted
+  #[test]
+  fn redacted_requests() {
+    let source = SourceConfig {
+      url: "http://facts/dog?key=${TEMPLATE_TEST_KEY}#top".to_string(),
+      query: HashMap::from([(
+        "token".to_string(),
+        "${TEMPLATE_TEST_KEY}".to_string(),
+      )]),
+      ..Default::default()
+    };
+    let request = EndpointRequest::render(&source, &VARS).unwrap();
+    assert_eq!(
+      request.redacted(),
+      "GET http://facts/dog?key=<redacted>&token=<redacted>"
+    );
+    let printed = format!("{:?}", request);
+    assert!(!printed.contains("secret"), "{}", printed);
+    assert!(
+      printed.contains(r#"("token", "<redacted>")"#),
+      "{}",
+      printed
+    );
+  }
+
+  /// Unknown, unset and unclosed variables are errors
+  #[test]
+  fn rendering_invalid() {
+    for template in [
+      "{{unknown}}",
+      "${TEMPLATE_TEST_UNSET}",
+      "{{count",
+      "${TEMPLATE_TEST_KEY",
+    ] {
+      assert!(render(template, &VARS).is_err(), "{}", template);
+    }
+  }
+}
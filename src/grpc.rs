@@ -0,0 +1,360 @@
+//! gRPC facts service
+//!
+//! Serving the `FactService` defined in `proto/facts.proto` on its own address
+//! next to the HTTP server, sharing the same `FactResolver` instance. The
+//! server reflection service is registered, so tools like `grpcurl` can
+//! discover the API without the proto files.
+
+use crate::config::ServerConfig;
+use crate::facts::{FactResolver, ResolvedFact};
+use crate::handlers::stream_interval;
+use futures::{stream, Stream};
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+use tokio::time::MissedTickBehavior;
+use tonic::{transport::Server, Request, Response, Status};
+use tracing::{info, instrument, trace};
+
+/// Generated protobuf messages and service traits
+pub mod proto {
+  #![allow(missing_docs)]
+  tonic::include_proto!("facts.v1");
+
+  /// Encoded file descriptor set used by the reflection service
+  pub const FILE_DESCRIPTOR_SET: &[u8] =
+    tonic::include_file_descriptor_set!("facts_descriptor");
+}
+
+use proto::{
+  fact_service_server::{FactService, FactServiceServer},
+  Fact, GetFactRequest, GetFactsRequest, GetFactsResponse, ListAnimalsRequest,
+  ListAnimalsResponse, StreamFactsRequest,
+};
+
+/// `FactService` implementation over the facts resolver
+#[derive(Debug, Clone)]
+pub struct FactGrpcService {
+  fact_resolver: FactResolver,
+  server_config: Arc<ServerConfig>,
+}
+
+impl FactGrpcService {
+  pub fn new(
+    fact_resolver: FactResolver,
+    server_config: Arc<ServerConfig>,
+  ) -> Self {
+    FactGrpcService {
+      fact_resolver,
+      server_config,
+    }
+  }
+
+  /// Checking the requested animal is configured
+  #[allow(clippy::result_large_err)]
+  fn check_animal(&self, animal: Option<&str>) -> Result<(), Status> {
+    match animal {
+      Some(animal)
+        if !self.fact_resolver.animals().iter().any(|a| a == animal) =>
+      {
+        Err(Status::not_found(format!(
+          "unknown animal type: {}",
+          animal
+        )))
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Resolving the fact for the animal or the configured default one
+async fn resolve_fact(
+  fact_resolver: &FactResolver,
+  animal: Option<&str>,
+) -> Result<Fact, Status> {
+  let result = match animal {
+    Some(animal) => fact_resolver.get_animal_fact(animal).await,
+    None => fact_resolver.get_fact().await,
+  };
+  let resolved = result.map_err(|err| Status::unavailable(err.to_string()))?;
+  Ok(Fact::from(resolved))
+}
+
+impl From<ResolvedFact> for Fact {
+  fn from(resolved: ResolvedFact) -> Self {
+    Fact {
+      fact: resolved.fact.text,
+      animal: resolved.animal,
+      stale: resolved.stale,
+    }
+  }
+}
+
+#[tonic::async_trait]
+impl FactService for FactGrpcService {
+  #[instrument(skip(self))]
+  async fn get_fact(
+    &self,
+    request: Request<GetFactRequest>,
+  ) -> Result<Response<Fact>, Status> {
+    trace!("handling the grpc fact request");
+    let animal = request.into_inner().animal;
+    self.check_animal(animal.as_deref())?;
+    let fact = resolve_fact(&self.fact_resolver, animal.as_deref()).await?;
+    Ok(Response::new(fact))
+  }
+
+  #[instrument(skip(self))]
+  async fn get_facts(
+    &self,
+    request: Request<GetFactsRequest>,
+  ) -> Result<Response<GetFactsResponse>, Status> {
+    trace!("handling the grpc facts request");
+    let GetFactsRequest {
+      animal,
+      count,
+      language,
+    } = request.into_inner();
+    self.check_animal(animal.as_deref())?;
+    let max_count = self.server_config.grpc.max_count;
+    if count > max_count {
+      return Err(Status::invalid_argument(format!(
+        "count is limited to {} facts",
+        max_count
+      )));
+    }
+
+    let resolved = self
+      .fact_resolver
+      .get_facts(animal.as_deref(), count as usize, language.as_deref())
+      .await
+      .map_err(|err| Status::unavailable(err.to_string()))?;
+    let facts = resolved.into_iter().map(Fact::from).collect();
+    Ok(Response::new(GetFactsResponse { facts }))
+  }
+
+  #[instrument(skip(self))]
+  async fn list_animals(
+    &self,
+    _request: Request<ListAnimalsRequest>,
+  ) -> Result<Response<ListAnimalsResponse>, Status> {
+    trace!("handling the grpc animals request");
+    Ok(Response::new(ListAnimalsResponse {
+      animals: self.fact_resolver.animals(),
+    }))
+  }
+
+  type StreamFactsStream =
+    Pin<Box<dyn Stream<Item = Result<Fact, Status>> + Send + 'static>>;
+
+  #[instrument(skip(self))]
+  async fn stream_facts(
+    &self,
+    request: Request<StreamFactsRequest>,
+  ) -> Result<Response<Self::StreamFactsStream>, Status> {
+    trace!("handling the grpc facts stream request");
+    let StreamFactsRequest { animal, interval } = request.into_inner();
+    self.check_animal(animal.as_deref())?;
+    let mut ticker = tokio::time::interval(stream_interval(
+      &self.server_config.stream,
+      interval,
+    ));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let fact_resolver = self.fact_resolver.clone();
+    let facts = stream::unfold(ticker, move |mut ticker| {
+      let fact_resolver = fact_resolver.clone();
+      let animal = animal.clone();
+      async move {
+        ticker.tick().await;
+        let fact = resolve_fact(&fact_resolver, animal.as_deref()).await;
+        Some((fact, ticker))
+      }
+    });
+    Ok(Response::new(Box::pin(facts)))
+  }
+}
+
+/// Building the gRPC router with the facts and reflection services
+pub fn router(
+  fact_resolver: FactResolver,
+  server_config: Arc<ServerConfig>,
+) -> tonic::transport::server::Router {
+  let reflection = tonic_reflection::server::Builder::configure()
+    .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+    .build()
+    .expect("failed to build the grpc reflection service");
+
+  Server::builder()
+    .add_service(FactServiceServer::new(FactGrpcService::new(
+      fact_resolver,
+      server_config,
+    )))
+    .add_service(reflection)
+}
+
+/// Serving the gRPC API on the configured address
+pub async fn serve(
+  fact_resolver: FactResolver,
+  server_config: Arc<ServerConfig>,
+) -> anyhow::Result<()> {
+  let address: SocketAddr = server_config.grpc.address.parse()?;
+  info!("grpc server is starting to listen on {}", address);
+  router(fact_resolver, server_config).serve(address).await?;
+  Ok(())
+}
+
+/// Integration tests against the in-process server listening on a local port
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util::{dog_resolver, DOG_FACT};
+  use futures::StreamExt;
+  use mockito::{Mock, Server};
+  use proto::fact_service_client::FactServiceClient;
+  use tokio::net::TcpListener;
+  use tokio_stream::wrappers::TcpListenerStream;
+  use tonic::{transport::Channel, Code};
+  use tonic_reflection::pb::{
+    server_reflection_client::ServerReflectionClient,
+    server_reflection_request::MessageRequest,
+    server_reflection_response::MessageResponse, ServerReflectionRequest,
+  };
+
+  /// Spawning the gRPC server with the mocked dog facts endpoint and
+  /// returning the address it is listening on
+  async fn spawn_server(server: &mut Server, hits: usize) -> (String, Mock) {
+    let (fact_resolver, mock) = dog_resolver(server, Some(hits)).await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let router = router(fact_resolver, Arc::new(ServerConfig::default()));
+    tokio::spawn(async move {
+      router
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+        .unwrap();
+    });
+    (format!("http://{}", address), mock)
+  }
+
+  /// Connecting the facts client to the server
+  async fn client(address: String) -> FactServiceClient<Channel> {
+    FactServiceClient::connect(address).await.unwrap()
+  }
+
+  #[tokio::test]
+  async fn get_fact() {
+    let mut server = Server::new_async().await;
+    let (address, mock) = spawn_server(&mut server, 1).await;
+
+    let fact = client(address)
+      .await
+      .get_fact(GetFactRequest { animal: None })
+      .await
+      .unwrap()
+      .into_inner();
+
+    assert_eq!(fact.animal, "dog", "animal type is not as expected");
+    assert_eq!(fact.fact, DOG_FACT, "facts are different");
+    mock.assert();
+  }
+
+  #[tokio::test]
+  async fn get_facts() {
+    let mut server = Server::new_async().await;
+    let (address, mock) = spawn_server(&mut server, 3).await;
+    let mut client = client(address).await;
+
+    let response = client
+      .get_facts(GetFactsRequest {
+        animal: Some("dog".into()),
+        count: 3,
+        language: None,
+      })
+      .await
+      .unwrap()
+      .into_inner();
+    assert_eq!(response.facts.len(), 3);
+    mock.assert();
+
+    let status = client
+      .get_facts(GetFactsRequest {
+        animal: None,
+        count: 1000,
+        language: None,
+      })
+      .await
+      .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+  }
+
+  #[tokio::test]
+  async fn list_animals_and_unknown_animal() {
+    let mut server = Server::new_async().await;
+    let (address, _mock) = spawn_server(&mut server, 0).await;
+    let mut client = client(address).await;
+
+    let response = client
+      .list_animals(ListAnimalsRequest {})
+      .await
+      .unwrap()
+      .into_inner();
+    assert_eq!(response.animals, vec!["dog".to_string()]);
+
+    let status = client
+      .get_fact(GetFactRequest {
+        animal: Some("parrot".into()),
+      })
+      .await
+      .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+  }
+
+  #[tokio::test]
+  async fn stream_facts() {
+    let mut server = Server::new_async().await;
+    let (address, _mock) = spawn_server(&mut server, 1).await;
+
+    let mut facts = client(address)
+      .await
+      .stream_facts(StreamFactsRequest {
+        animal: Some("dog".into()),
+        interval: Some(60),
+      })
+      .await
+      .unwrap()
+      .into_inner();
+
+    let fact = facts.next().await.unwrap().unwrap();
+    assert_eq!(fact.fact, DOG_FACT, "facts are different");
+  }
+
+  /// Reflection service should list the facts service
+  #[tokio::test]
+  async fn reflection_lists_service() {
+    let mut server = Server::new_async().await;
+    let (address, _mock) = spawn_server(&mut server, 0).await;
+
+    let channel = Channel::from_shared(address)
+      .unwrap()
+      .connect()
+      .await
+      .unwrap();
+    let mut client = ServerReflectionClient::new(channel);
+    let request = ServerReflectionRequest {
+      host: String::new(),
+      message_request: Some(MessageRequest::ListServices(String::new())),
+    };
+    let mut responses = client
+      .server_reflection_info(futures::stream::iter([request]))
+      .await
+      .unwrap()
+      .into_inner();
+
+    let response = responses.next().await.unwrap().unwrap();
+    let services = match response.message_response {
+      Some(MessageResponse::ListServicesResponse(list)) => list.service,
+      _ => panic!("list services response expected"),
+    };
+    assert!(services.iter().any(|s| s.name == "facts.v1.FactService"));
+  }
+}
@@ -0,0 +1,302 @@
+//! Fact of the day for the `/fact/daily` route.
+//!
+//! Everybody requesting the same animal and date gets the same fact. The date
+//! defaults to the current date in the requested time zone, and the response is
+//! cacheable until the next midnight of that time zone. The facts of the past
+//! dates never change, so they are cacheable for a year.
+
+use super::{error_response, FactResponse};
+use crate::facts::FactResolver;
+use axum::{
+  extract::{Query, State},
+  http::{header, StatusCode},
+  response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Days, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use tracing::{instrument, trace};
+use utoipa::IntoParams;
+
+/// Query parameters of the `/fact/daily` route
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DailyParams {
+  /// Animal type of the fact, the configured default is used when omitted
+  animal: Option<String>,
+  /// Date in the `YYYY-MM-DD` format, today in the time zone when omitted
+  date: Option<String>,
+  /// IANA time zone name like `Europe/Berlin`, `UTC` when omitted
+  tz: Option<String>,
+}
+
+/// Maximum age of the past dates responses, a year like for immutable assets
+const PAST_DATE_MAX_AGE: u64 = 365 * 24 * 3600;
+
+/// `Cache-Control` of the daily fact response. The stale fallback fact isn't
+/// pinned, so it isn't cached, the past dates are immutable and today expires at
+/// the next midnight of the time zone.
+fn cache_control(
+  now: DateTime<Utc>,
+  tz: Tz,
+  date: NaiveDate,
+  stale: bool,
+) -> String {
+  if stale {
+    "no-cache".to_string()
+  } else if date < now.with_timezone(&tz).date_naive() {
+    format!("public, max-age={}, immutable", PAST_DATE_MAX_AGE)
+  } else {
+    format!("public, max-age={}", seconds_until_midnight(now, tz))
+  }
+}
+
+/// Seconds from now until the next midnight of the time zone
+fn seconds_until_midnight(now: DateTime<Utc>, tz: Tz) -> i64 {
+  let tomorrow = now.with_timezone(&tz).date_naive() + Days::new(1);
+  let midnight = tomorrow.and_hms_opt(0, 0, 0).unwrap();
+  // The midnight may not exist when the daylight saving time starts at it
+  let next_midnight = tz
+    .from_local_datetime(&midnight)
+    .earliest()
+    .unwrap_or_else(|| tz.from_utc_datetime(&midnight));
+  (next_midnight.with_timezone(&Utc) - now)
+    .num_seconds()
+    .max(0)
+}
+
+/// Handler for the `/fact/daily` route. Responds with the fact of the day or
+/// `400 Bad Request` for the unknown animal, invalid date or time zone.
+#[utoipa::path(
+  get,
+  path = "/fact/daily",
+  params(DailyParams),
+  responses(
+    (status = 200, description = "Fact of the day, the same for the animal and date", body = FactResponse,
+      headers(("Cache-Control" = String, description = "Expiring at the next midnight of the time zone, immutable for the past dates"))),
+    (status = 400, description = "Unknown animal type, invalid date or time zone", body = String, content_type = "text/plain"),
+    (status = 500, description = "Fact resolving error", body = String, content_type = "text/plain"),
+    (status = 502, description = "Upstream contract broken, the endpoint response violates the source schema, or all the facts of the endpoint rejected by the filter", body = String, content_type = "text/plain")
+  )
+)]
+#[instrument(skip(fact_resolver))]
+pub async fn daily_fact_handler(
+  State(fact_resolver): State<FactResolver>,
+  Query(params): Query<DailyParams>,
+) -> Response {
+  trace!("handling the daily fact request");
+  let tz: Tz = match params.tz.as_deref().unwrap_or("UTC").parse() {
+    Ok(tz) => tz,
+    Err(_) => {
+      return (StatusCode::BAD_REQUEST, "invalid time zone").into_response()
+    }
+  };
+  let now = Utc::now();
+  let today = now.with_timezone(&tz).date_naive();
+  let date = match params.date.as_deref().map(str::parse::<NaiveDate>) {
+    None => today,
+    Some(Ok(date)) if date <= today => date,
+    Some(Ok(_)) => {
+      return (StatusCode::BAD_REQUEST, "date is in the future").into_response()
+    }
+    Some(Err(_)) => {
+      return (StatusCode::BAD_REQUEST, "invalid date, expected YYYY-MM-DD")
+        .into_response()
+    }
+  };
+
+  let animal = match params.animal {
+    Some(animal) if !fact_resolver.animals().contains(&animal) => {
+      return (
+        StatusCode::BAD_REQUEST,
+        format!("unknown animal type: {}", animal),
+      )
+        .into_response()
+    }
+    Some(animal) => animal,
+    None => match fact_resolver.daily_animal(date) {
+      Some(animal) => animal,
+      None => {
+        return (StatusCode::BAD_REQUEST, "no animals configured")
+          .into_response()
+      }
+    },
+  };
+
+  match fact_resolver.daily_fact(&animal, date).await {
+    Ok(resolved) => (
+      StatusCode::OK,
+      [(
+        header::CACHE_CONTROL,
+        cache_control(now, tz, date, resolved.stale),
+      )],
+      Json(FactResponse::from(resolved)),
+    )
+      .into_response(),
+    Err(err) => error_response(err),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::ServerConfig;
+  use crate::facts::adapters::dog::DogFactEndpointResponse;
+  use crate::facts::config::FactsConfig;
+  use crate::facts::store::FactStore;
+  use crate::handlers::app;
+  use axum::{body::Body, http::Request, Router};
+  use mockito::Server;
+  use std::collections::HashMap;
+  use tempfile::NamedTempFile;
+  use tower::ServiceExt;
+
+  /// Requesting the route, returning the status, `Cache-Control` header and
+  /// body
+  async fn request(app: &Router, uri: &str) -> (StatusCode, String, String) {
+    let response = app
+      .clone()
+      .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+    let status = response.status();
+    let cache_control = response
+      .headers()
+      .get(header::CACHE_CONTROL)
+      .map(|value| value.to_str().unwrap().to_string())
+      .unwrap_or_default();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (
+      status,
+      cache_control,
+      String::from_utf8(body.to_vec()).unwrap(),
+    )
+  }
+
+  /// The daily fact is fetched once, pinned in the store and served after the
+  /// restart even when the endpoint serves other facts
+  #[tokio::test]
+  async fn pinned_daily_fact() {
+    // Create a mock (mockito) API endpoint server for dog facts
+    let mut server = Server::new_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["first dog fact".to_string()],
+      success: true,
+    };
+    let first_mock = server
+      .mock("GET", "/somefacts")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .expect(1)
+      .create_async()
+      .await;
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([(
+        "dog".to_string(),
+        (server.url() + "/somefacts").into(),
+      )]),
+      ..Default::default()
+    };
+    let file = NamedTempFile::new().unwrap();
+    let app_with_store = || {
+      let store = FactStore::open(file.path()).unwrap();
+      app(
+        FactResolver::new(&facts_config).with_store(store),
+        &ServerConfig::default(),
+      )
+    };
+
+    let first_app = app_with_store();
+    let uri = "/fact/daily?date=2024-02-29";
+    let (status, _, first_body) = request(&first_app, uri).await;
+    assert_eq!(status, StatusCode::OK, "{}", first_body);
+    let (_, _, body) = request(&first_app, uri).await;
+    assert_eq!(body, first_body, "daily fact changed");
+    first_mock.assert();
+
+    // Restarting with the endpoint serving another fact
+    first_mock.remove_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["second dog fact".to_string()],
+      success: true,
+    };
+    server
+      .mock("GET", "/somefacts")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .create_async()
+      .await;
+    let second_app = app_with_store();
+    let (_, _, body) = request(&second_app, uri).await;
+    assert_eq!(body, first_body, "daily fact changed after the restart");
+    let response_json: FactResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(response_json.fact, "first dog fact");
+
+    // Another date is chosen from the stored facts without fetching
+    let (status, _, body) =
+      request(&second_app, "/fact/daily?animal=dog&date=2024-03-01").await;
+    assert_eq!(status, StatusCode::OK);
+    let response_json: FactResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(response_json.fact, "first dog fact");
+  }
+
+  /// Invalid parameters are rejected and the response expires at midnight
+  #[tokio::test]
+  async fn parameters_and_caching() {
+    let config_data = crate::config::ConfigData::new("config.offline.json")
+      .expect("offline configuration loading error");
+    let app = app(FactResolver::new(&config_data.animals), &config_data.server);
+
+    for uri in [
+      "/fact/daily?tz=Mars/Olympus",
+      "/fact/daily?date=yesterday",
+      "/fact/daily?date=2999-01-01",
+      "/fact/daily?animal=fox",
+    ] {
+      let (status, _, _) = request(&app, uri).await;
+      assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+
+    let (status, cache_control, body) =
+      request(&app, "/fact/daily?tz=Asia/Tokyo").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let max_age: i64 = cache_control
+      .strip_prefix("public, max-age=")
+      .unwrap()
+      .parse()
+      .unwrap();
+    assert!((0..=86400).contains(&max_age), "max-age {}", max_age);
+    let (_, _, same_body) = request(&app, "/fact/daily?tz=Asia/Tokyo").await;
+    assert_eq!(body, same_body, "daily fact changed");
+  }
+
+  /// Past dates are immutable and the stale facts aren't cached
+  #[test]
+  fn caching_of_the_dates() {
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 22, 0, 0).unwrap();
+    let today = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let yesterday = NaiveDate::from_ymd_opt(2023, 12, 31).unwrap();
+    assert_eq!(
+      cache_control(now, Tz::UTC, today, false),
+      "public, max-age=7200"
+    );
+    assert_eq!(
+      cache_control(now, Tz::UTC, yesterday, false),
+      "public, max-age=31536000, immutable"
+    );
+    assert_eq!(cache_control(now, Tz::UTC, yesterday, true), "no-cache");
+    // It's already January 2 in Tokyo
+    assert_eq!(
+      cache_control(now, Tz::Asia__Tokyo, today, false),
+      "public, max-age=31536000, immutable"
+    );
+  }
+
+  /// Seconds until midnight depend on the time zone
+  #[test]
+  fn midnight_of_the_time_zone() {
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 22, 0, 0).unwrap();
+    assert_eq!(seconds_until_midnight(now, Tz::UTC), 2 * 3600);
+    assert_eq!(seconds_until_midnight(now, Tz::Europe__Berlin), 3600);
+    assert_eq!(seconds_until_midnight(now, Tz::Asia__Tokyo), 17 * 3600);
+  }
+}
@@ -0,0 +1,294 @@
+//! GraphQL API over the facts resolver.
+//!
+//! Exposes the `fact`, `facts` and `animals` queries at `/graphql` and the
+//! `facts` subscription over the WebSocket at `/graphql/ws`. The GraphiQL
+//! playground is served at `GET /graphql` when enabled in the configuration.
+
+use super::{stream::stream_interval, AppState, FactFields, FactResponse};
+use crate::config::ServerConfig;
+use crate::facts::FactResolver;
+use async_graphql::{
+  http::GraphiQLSource, Context, EmptyMutation, Object, Result, Schema,
+  Subscription,
+};
+use async_graphql_axum::{GraphQL, GraphQLSubscription};
+use axum::{
+  response::{Html, IntoResponse},
+  routing::{get, post_service},
+  Router,
+};
+use futures::{stream, Stream};
+use std::sync::Arc;
+use tokio::time::MissedTickBehavior;
+use tracing::{instrument, trace};
+
+/// GraphQL endpoint path
+const ENDPOINT: &str = "/graphql";
+
+/// GraphQL subscriptions WebSocket endpoint path
+const SUBSCRIPTION_ENDPOINT: &str = "/graphql/ws";
+
+/// Facts GraphQL schema
+pub type FactSchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+/// Resolving the fact for the animal or the configured default one
+async fn resolve_fact(
+  fact_resolver: &FactResolver,
+  animal: Option<&str>,
+) -> Result<FactResponse> {
+  let resolved = match animal {
+    Some(animal) => fact_resolver.get_animal_fact(animal).await?,
+    None => fact_resolver.get_fact().await?,
+  };
+  // GraphQL queries select the metadata fields themselves
+  Ok(FactResponse::with_fields(resolved, &FactFields::all()))
+}
+
+/// Root of the GraphQL queries
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+  /// Fact about the animal, the configured default animal is used when omitted
+  async fn fact(
+    &self,
+    ctx: &Context<'_>,
+    animal: Option<String>,
+  ) -> Result<FactResponse> {
+    let fact_resolver = ctx.data_unchecked::<FactResolver>();
+    resolve_fact(fact_resolver, animal.as_deref()).await
+  }
+
+  /// Several facts about the animal in the language, the configured one is
+  /// used when omitted. The endpoints returning several facts per response
+  /// are requested once.
+  async fn facts(
+    &self,
+    ctx: &Context<'_>,
+    animal: Option<String>,
+    count: u32,
+    language: Option<String>,
+  ) -> Result<Vec<FactResponse>> {
+    let max_count = ctx.data_unchecked::<Arc<ServerConfig>>().graphql.max_count;
+    if count > max_count {
+      return Err(format!("count is limited to {} facts", max_count).into());
+    }
+
+    let fact_resolver = ctx.data_unchecked::<FactResolver>();
+    let resolved = fact_resolver
+      .get_facts(animal.as_deref(), count as usize, language.as_deref())
+      .await?;
+    Ok(
+      resolved
+        .into_iter()
+        .map(|resolved| FactResponse::with_fields(resolved, &FactFields::all()))
+        .collect(),
+    )
+  }
+
+  /// Names of the configured animals
+  async fn animals(&self, ctx: &Context<'_>) -> Vec<String> {
+    ctx.data_unchecked::<FactResolver>().animals()
+  }
+}
+
+/// Root of the GraphQL subscriptions
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+  /// Stream of facts about the animal every `interval` seconds, bounded the
+  /// same way as the `/fact/stream` route
+  async fn facts(
+    &self,
+    ctx: &Context<'_>,
+    animal: Option<String>,
+    interval: Option<u64>,
+  ) -> impl Stream<Item = Result<FactResponse>> {
+    let fact_resolver = ctx.data_unchecked::<FactResolver>().clone();
+    let server_config = ctx.data_unchecked::<Arc<ServerConfig>>();
+    let mut ticker =
+      tokio::time::interval(stream_interval(&server_config.stream, interval));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    stream::unfold(ticker, move |mut ticker| {
+      let fact_resolver = fact_resolver.clone();
+      let animal = animal.clone();
+      async move {
+        ticker.tick().await;
+        let fact = resolve_fact(&fact_resolver, animal.as_deref()).await;
+        Some((fact, ticker))
+      }
+    })
+  }
+}
+
+/// Building the schema with the resolver and configuration as the context data
+pub fn schema(
+  fact_resolver: FactResolver,
+  server_config: Arc<ServerConfig>,
+) -> FactSchema {
+  Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot)
+    .data(fact_resolver)
+    .data(server_config)
+    .finish()
+}
+
+/// Router serving the GraphQL endpoints and optionally the playground
+pub fn graphql_router(
+  fact_resolver: FactResolver,
+  server_config: Arc<ServerConfig>,
+) -> Router<AppState> {
+  trace!("configuring graphql router");
+  let playground = server_config.graphql.playground;
+  let schema = schema(fact_resolver, server_config);
+  let endpoint = if playground {
+    get(graphiql_handler).post_service(GraphQL::new(schema.clone()))
+  } else {
+    post_service(GraphQL::new(schema.clone()))
+  };
+
+  Router::new()
+    .route(ENDPOINT, endpoint)
+    .route_service(SUBSCRIPTION_ENDPOINT, GraphQLSubscription::new(schema))
+}
+
+/// Handler serving the GraphiQL playground page
+#[instrument]
+async fn graphiql_handler() -> impl IntoResponse {
+  trace!("serving the graphiql playground");
+  Html(
+    GraphiQLSource::build()
+      .endpoint(ENDPOINT)
+      .subscription_endpoint(SUBSCRIPTION_ENDPOINT)
+      .finish(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::GraphQLConfig;
+  use crate::handlers::app;
+  use crate::test_util::{dog_resolver, DOG_FACT};
+  use async_graphql::Request;
+  use axum::{body::Body, http::StatusCode};
+  use futures::StreamExt;
+  use mockito::Server;
+  use tower::ServiceExt;
+
+  /// Server configuration with the enabled GraphQL endpoint
+  fn graphql_config(playground: bool) -> ServerConfig {
+    ServerConfig {
+      graphql: GraphQLConfig {
+        enabled: true,
+        playground,
+        ..Default::default()
+      },
+      ..Default::default()
+    }
+  }
+
+  /// Fetching the fact and the animals in a single query
+  #[tokio::test]
+  async fn fact_and_animals_query() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, mock) = dog_resolver(&mut server, Some(1)).await;
+    let schema = schema(fact_resolver, Arc::new(graphql_config(false)));
+
+    let response = schema
+      .execute(r#"{ fact(animal: "dog") { fact animal } animals }"#)
+      .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["fact"]["fact"], DOG_FACT);
+    assert_eq!(data["fact"]["animal"], "dog");
+    assert_eq!(data["animals"], serde_json::json!(["dog"]));
+    mock.assert();
+  }
+
+  /// Requesting several facts and exceeding the configured count limit
+  #[tokio::test]
+  async fn facts_query() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, mock) = dog_resolver(&mut server, Some(3)).await;
+    let schema = schema(fact_resolver, Arc::new(graphql_config(false)));
+
+    let response = schema.execute("{ facts(count: 3) { fact } }").await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["facts"].as_array().unwrap().len(), 3);
+    mock.assert();
+
+    let response = schema.execute("{ facts(count: 1000) { fact } }").await;
+    assert!(!response.errors.is_empty(), "count limit error expected");
+  }
+
+  /// Receiving the first fact from the subscription stream
+  #[tokio::test]
+  async fn facts_subscription() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, _mock) = dog_resolver(&mut server, Some(1)).await;
+    let schema = schema(fact_resolver, Arc::new(graphql_config(false)));
+
+    let mut stream = schema.execute_stream(Request::new(
+      r#"subscription { facts(animal: "dog", interval: 60) { fact } }"#,
+    ));
+    let response = stream.next().await.unwrap();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["facts"]["fact"], DOG_FACT);
+  }
+
+  /// The endpoint and the playground are served only when enabled
+  #[tokio::test]
+  async fn endpoint_toggles() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, _mock) = dog_resolver(&mut server, Some(0)).await;
+    let get_graphql = || {
+      axum::http::Request::builder()
+        .uri(ENDPOINT)
+        .body(Body::empty())
+        .unwrap()
+    };
+
+    let disabled = app(fact_resolver.clone(), &ServerConfig::default());
+    let response = disabled.oneshot(get_graphql()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let without_playground = app(fact_resolver.clone(), &graphql_config(false));
+    let response = without_playground.oneshot(get_graphql()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let with_playground = app(fact_resolver, &graphql_config(true));
+    let response = with_playground.oneshot(get_graphql()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+  }
+
+  /// Executing the query over HTTP
+  #[tokio::test]
+  async fn http_query() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, _mock) = dog_resolver(&mut server, Some(0)).await;
+    let app = app(fact_resolver, &graphql_config(false));
+
+    let response = app
+      .oneshot(
+        axum::http::Request::builder()
+          .method("POST")
+          .uri(ENDPOINT)
+          .header("content-type", "application/json")
+          .body(Body::from(r#"{"query":"{ animals }"}"#))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response["data"]["animals"], serde_json::json!(["dog"]));
+  }
+}
@@ -0,0 +1,1506 @@
+//! Handling axum webserver requests and tests.
+//! Providing axum router by the `app()` function.
+
+use crate::config::ServerConfig;
+use crate::facts;
+use crate::facts::filter::FactsRejected;
+use crate::facts::schema::ContractBroken;
+use crate::facts::store::content_hash;
+use async_graphql::SimpleObject;
+use axum::{
+  extract::{FromRef, Query, State},
+  http::{header, HeaderMap, StatusCode},
+  response::{IntoResponse, Json, Response},
+  routing::get,
+  Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::trace;
+use utoipa::{IntoParams, ToSchema};
+
+mod daily;
+mod graphql;
+mod openapi;
+mod search;
+mod stream;
+mod ws;
+
+pub(crate) use stream::stream_interval;
+
+/// `Warning` header value of the responses with the stale facts
+const STALE_WARNING: &str = "110 - \"Response is Stale\"";
+
+/// Cookie of the client session identifying the client
+const SESSION_COOKIE: &str = "session";
+
+/// Header of the client API key identifying the client
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Header of the client id identifying the client
+const CLIENT_ID_HEADER: &str = "x-client-id";
+
+/// API fact route JSON response format
+#[derive(Clone, Deserialize, Serialize, ToSchema, SimpleObject)]
+#[graphql(name = "Fact")]
+pub struct FactResponse {
+  /// Fact text
+  fact: String,
+  /// Animal type the fact is about
+  animal: String,
+  /// The fact is served from the recently resolved facts because the animal
+  /// endpoint request failed
+  stale: bool,
+  /// The client has seen the fact recently, but no other fact was found
+  repeated: bool,
+  /// Id of the fact at the endpoint
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  id: Option<String>,
+  /// URL the fact comes from
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  source: Option<String>,
+  /// Time the fact was created at the endpoint
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  created_at: Option<String>,
+  /// Language code of the fact text
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  language: Option<String>,
+  /// Tags of the fact like `verified`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  tags: Vec<String>,
+  /// Author or origin of the fact to credit
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  attribution: Option<String>,
+}
+
+impl From<facts::ResolvedFact> for FactResponse {
+  fn from(resolved: facts::ResolvedFact) -> Self {
+    FactResponse::with_fields(resolved, &FactFields::default())
+  }
+}
+
+impl FactResponse {
+  /// Response of the resolved fact including the selected metadata fields
+  fn with_fields(resolved: facts::ResolvedFact, fields: &FactFields) -> Self {
+    let fact = resolved.fact;
+    FactResponse {
+      fact: fact.text,
+      animal: resolved.animal,
+      stale: resolved.stale,
+      repeated: resolved.repeated,
+      id: fact.id.filter(|_| fields.id),
+      source: fact.source.filter(|_| fields.source),
+      created_at: fact.created_at.filter(|_| fields.created_at),
+      language: fact.language.filter(|_| fields.language),
+      tags: if fields.tags { fact.tags } else { Vec::new() },
+      attribution: fact.attribution.filter(|_| fields.attribution),
+    }
+  }
+}
+
+/// Metadata fields of the fact included into the response
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FactFields {
+  id: bool,
+  source: bool,
+  created_at: bool,
+  language: bool,
+  tags: bool,
+  attribution: bool,
+}
+
+impl FactFields {
+  /// All the metadata fields
+  fn all() -> Self {
+    FactFields {
+      id: true,
+      source: true,
+      created_at: true,
+      language: true,
+      tags: true,
+      attribution: true,
+    }
+  }
+
+  /// Fields selected by the comma separated field names, all of them when
+  /// verbose. Errors on the unknown field names.
+  fn select(fields: Option<&str>, verbose: bool) -> Result<Self, String> {
+    if verbose {
+      return Ok(FactFields::all());
+    }
+    let mut selected = FactFields::default();
+    for field in fields.unwrap_or("").split(',').map(str::trim) {
+      match field {
+        "" => {}
+        "id" => selected.id = true,
+        "source" => selected.source = true,
+        "created_at" => selected.created_at = true,
+        "language" => selected.language = true,
+        "tags" => selected.tags = true,
+        "attribution" => selected.attribution = true,
+        _ => return Err(format!("unknown fact field: {}", field)),
+      }
+    }
+    Ok(selected)
+  }
+}
+
+/// Query parameters of the `/fact` route
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FactParams {
+  /// Comma separated metadata fields to include: `id`, `source`,
+  /// `created_at`, `language`, `tags` and `attribution`
+  fields: Option<String>,
+  /// Include all the metadata fields
+  #[serde(default)]
+  verbose: bool,
+}
+
+/// Identity of the client used to suppress the facts it has seen recently: the
+/// session cookie, the API key (hashed) or the `X-Client-Id` header
+fn client_id(headers: &HeaderMap) -> Option<String> {
+  let session = headers
+    .get_all(header::COOKIE)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|cookies| cookies.split(';'))
+    .filter_map(|cookie| cookie.trim().split_once('='))
+    .find(|(name, value)| *name == SESSION_COOKIE && !value.is_empty())
+    .map(|(_, value)| format!("session:{}", value));
+  let header_value = |name: &str| {
+    headers
+      .get(name)
+      .and_then(|value| value.to_str().ok())
+      .filter(|value| !value.is_empty())
+  };
+  session
+    .or_else(|| {
+      header_value(API_KEY_HEADER)
+        .map(|key| format!("key:{}", content_hash(key)))
+    })
+    .or_else(|| {
+      header_value(CLIENT_ID_HEADER).map(|id| format!("client:{}", id))
+    })
+}
+
+/// Shared state of the router. Handlers extract the parts they need by the
+/// `FromRef` implementations below.
+#[derive(Clone)]
+pub struct AppState {
+  fact_resolver: facts::FactResolver,
+  server_config: Arc<ServerConfig>,
+  fact_hub: ws::FactHub,
+}
+
+impl FromRef<AppState> for facts::FactResolver {
+  fn from_ref(state: &AppState) -> Self {
+    state.fact_resolver.clone()
+  }
+}
+
+impl FromRef<AppState> for Arc<ServerConfig> {
+  fn from_ref(state: &AppState) -> Self {
+    state.server_config.clone()
+  }
+}
+
+impl FromRef<AppState> for ws::FactHub {
+  fn from_ref(state: &AppState) -> Self {
+    state.fact_hub.clone()
+  }
+}
+
+/// Axum router object returning router with routes, handlers and states
+pub fn app(
+  fact_resolver: facts::FactResolver,
+  server_config: &ServerConfig,
+) -> Router {
+  trace!("configuring app router");
+  let mut router = Router::new()
+    .route("/fact", get(facts_handler))
+    .route("/fact/daily", get(daily::daily_fact_handler))
+    .route("/fact/stream", get(stream::fact_stream_handler))
+    .route("/facts/search", get(search::search_handler))
+    .route("/ws", get(ws::ws_handler))
+    .route("/openapi.json", get(openapi::openapi_handler));
+  if server_config.docs {
+    router = router.merge(openapi::docs_router());
+  }
+  let server_config = Arc::new(server_config.clone());
+  if server_config.graphql.enabled {
+    router = router.merge(graphql::graphql_router(
+      fact_resolver.clone(),
+      server_config.clone(),
+    ));
+  }
+  router.with_state(AppState {
+    fact_hub: ws::FactHub::new(fact_resolver.clone()),
+    fact_resolver,
+    server_config,
+  })
+}
+
+/// Handler for the `/fact` route. It invoking facts resolver and return the result
+/// in a JSON format or an error string with the status code in case of something wrong.
+#[utoipa::path(
+  get,
+  path = "/fact",
+  params(
+    FactParams,
+    ("X-Client-Id" = Option<String>, Header, description = "Client id used to avoid the facts the client has seen recently, like the `session` cookie or the `X-Api-Key` header")
+  ),
+  responses(
+    (status = 200, description = "Fact of the configured animal", body = FactResponse,
+      headers(("Warning" = String, description = "`110 - \"Response is Stale\"` when the stale fact is served"))),
+    (status = 400, description = "Unknown metadata field", body = String, content_type = "text/plain"),
+    (status = 500, description = "Fact resolving error", body = String, content_type = "text/plain"),
+    (status = 502, description = "Upstream contract broken, the endpoint response violates the source schema, or all the facts of the endpoint rejected by the filter", body = String, content_type = "text/plain")
+  )
+)]
+async fn facts_handler(
+  State(fact_resolver): State<facts::FactResolver>,
+  Query(params): Query<FactParams>,
+  headers: HeaderMap,
+) -> Response {
+  trace!("handling the fact request through facts resolver");
+  let fields =
+    match FactFields::select(params.fields.as_deref(), params.verbose) {
+      Ok(fields) => fields,
+      Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+  let result = match client_id(&headers) {
+    Some(client) => fact_resolver.get_client_fact(None, &client).await,
+    None => fact_resolver.get_fact().await,
+  };
+  match result {
+    Ok(resolved) if resolved.stale => (
+      StatusCode::OK,
+      [(header::WARNING, STALE_WARNING)],
+      Json(FactResponse::with_fields(resolved, &fields)),
+    )
+      .into_response(),
+    Ok(resolved) => (
+      StatusCode::OK,
+      Json(FactResponse::with_fields(resolved, &fields)),
+    )
+      .into_response(),
+    Err(err) => error_response(err),
+  }
+}
+
+/// Error response of the fact resolving error, shared by the fact handlers so
+/// the same errors have the same status code on every route
+fn error_response(err: anyhow::Error) -> Response {
+  if err.downcast_ref::<ContractBroken>().is_some()
+    || err.downcast_ref::<FactsRejected>().is_some()
+  {
+    return (StatusCode::BAD_GATEWAY, err.to_string()).into_response();
+  }
+  (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+}
+
+/// Testing axum server by having an `app` function that produces our app.
+/// It makes easy to call it from tests without having to create an HTTP server itself.
+/// Fully listening server test is covered by the `listening_on_the_address` test.
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::facts::adapters::{
+    cat::CatFactEndpointResponse, dog::DogFactEndpointResponse,
+  };
+  use crate::facts::config::FactsConfig;
+  use crate::facts::store::FactStore;
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use mockito::Server;
+  use serde_json::json;
+  use std::collections::HashMap;
+  use std::net::TcpListener;
+  use tower::ServiceExt;
+
+  /// Requesting our facts server for the valid animal type fact from the mocked
+  /// dog facts server endpoint
+  #[tokio::test]
+  async fn valid_animal_type() {
+    // Create a mock (mockito) API endpoint server for dog facts
+    let mut server = Server::new_async().await;
+    let dog_fact_url = server.url() + "/somefacts";
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["some funny dog fact".to_string()],
+      success: true,
+    };
+    let dog_fact_server_mock = server
+      .mock("GET", "/somefacts")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .create_async()
+      .await;
+
+    // Creating facts resolver
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([("dog".to_string(), dog_fact_url.into())]),
+      ..Default::default()
+    };
+    let fact_resolver = facts::FactResolver::new(&facts_config);
+
+    // Creating an axum router and shoot a request
+    let app = app(fact_resolver, &ServerConfig::default());
+    let response = app
+      .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+
+    // Assertions
+    assert_eq!(
+      response.status(),
+      StatusCode::OK,
+      "server response status code is not OK"
+    );
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let response_json: FactResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(
+      response_json.animal, "dog",
+      "animal type is not as expected"
+    );
+    assert_eq!(
+      response_json.fact, dog_fact_response.facts[0],
+      "facts are different"
+    );
+
+    dog_fact_server_mock.assert();
+  }
+
+  /// Metadata fields of the fact are included only when selected by the
+  /// `fields` parameter or all of them with `verbose`
+  #[tokio::test]
+  async fn fact_metadata_fields() {
+    // Create a mock (mockito) API endpoint server for cat facts
+    let mut server = Server::new_async().await;
+    let cat_fact_response = CatFactEndpointResponse {
+      text: "some cat fact".to_string(),
+      animal_type: "cat".to_string(),
+      id: Some("cat-fact-id".to_string()),
+      user: Some("cat-fact-user".to_string()),
+      ..Default::default()
+    };
+    server
+      .mock("GET", "/catfacts")
+      .with_body(serde_json::to_string(&cat_fact_response).unwrap())
+      .expect_at_least(1)
+      .create_async()
+      .await;
+
+    let facts_config = FactsConfig {
+      default: "cat".into(),
+      facts: HashMap::from([(
+        "cat".to_string(),
+        (server.url() + "/catfacts").into(),
+      )]),
+      ..Default::default()
+    };
+    let app = app(
+      facts::FactResolver::new(&facts_config),
+      &ServerConfig::default(),
+    );
+    let request = |uri: &str| {
+      app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+    };
+
+    let response = request("/fact").await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let response_json: serde_json::Value =
+      serde_json::from_slice(&body).unwrap();
+    assert!(response_json.get("id").is_none(), "{}", response_json);
+
+    let response = request("/fact?fields=id,source").await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let response_json: FactResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response_json.id.as_deref(), Some("cat-fact-id"));
+    assert_eq!(
+      response_json.source,
+      Some(server.url() + "/catfacts"),
+      "source is not the endpoint URL"
+    );
+    assert!(response_json.attribution.is_none());
+
+    let response = request("/fact?verbose=true").await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let response_json: FactResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response_json.attribution.as_deref(), Some("cat-fact-user"));
+
+    let response = request("/fact?fields=id,unknown").await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  }
+
+  /// Extra facts of the multi-fact response are served to the next requests
+  /// without requesting the endpoint, the empty response is an error
+  #[tokio::test]
+  async fn multiple_facts_response() {
+    // Create a mock (mockito) API endpoint server for dog facts
+    let mut server = Server::new_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["first dog fact".to_string(), "second dog fact".to_string()],
+      success: true,
+    };
+    let dog_fact_server_mock = server
+      .mock("GET", "/somefacts")
+      .match_query(mockito::Matcher::UrlEncoded("number".into(), "2".into()))
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .expect(1)
+      .create_async()
+      .await;
+    let empty_response = DogFactEndpointResponse {
+      facts: Vec::new(),
+      success: true,
+    };
+    server
+      .mock("GET", "/nofacts")
+      .with_body(serde_json::to_string(&empty_response).unwrap())
+      .create_async()
+      .await;
+
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([(
+        "dog".to_string(),
+        (server.url() + "/somefacts?number=2").into(),
+      )]),
+      ..Default::default()
+    };
+    let fact_resolver = facts::FactResolver::new(&facts_config);
+
+    let mut facts = Vec::new();
+    for _n in 0..2 {
+      let resolved = fact_resolver.get_animal_fact("dog").await.unwrap();
+      assert!(!resolved.stale);
+      facts.push(resolved.fact.text);
+    }
+    assert_eq!(facts, dog_fact_response.facts);
+    dog_fact_server_mock.assert();
+
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([(
+        "dog".to_string(),
+        (server.url() + "/nofacts").into(),
+      )]),
+      ..Default::default()
+    };
+    let fact_resolver = facts::FactResolver::new(&facts_config);
+    let err = fact_resolver.get_animal_fact("dog").await.unwrap_err();
+    assert!(err.downcast_ref::<facts::adapters::NoFacts>().is_some());
+  }
+
+  /// Testing responses for `any` animal type
+  #[tokio::test]
+  async fn any_animal_type() {
+    // Create a mock (mockito) API endpoint server for dog facts
+    let mut dog_server = Server::new_async().await;
+    let dog_fact_url = dog_server.url() + "/somefacts";
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["some funny dog fact".to_string()],
+      success: true,
+    };
+    let dog_fact_server_mock = dog_server
+      .mock("GET", "/somefacts")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .expect_at_least(1)
+      .create_async()
+      .await;
+
+    // Create a mock (mockito) API endpoint server for cats facts
+    let mut cat_server = Server::new_async().await;
+    let cat_fact_url = cat_server.url() + "/somefacts";
+    let cat_fact_response = CatFactEndpointResponse {
+      text: "some funny cat fact".into(),
+      animal_type: "cat".into(),
+      deleted: false,
+      ..Default::default()
+    };
+    let cat_fact_server_mock = cat_server
+      .mock("GET", "/somefacts")
+      .with_body(serde_json::to_string(&cat_fact_response).unwrap())
+      .expect_at_least(1)
+      .create_async()
+      .await;
+
+    // Creating facts resolver with `any` type
+    let facts_config = FactsConfig {
+      default: "any".into(),
+      facts: HashMap::from([
+        ("dog".to_string(), dog_fact_url.into()),
+        ("cat".to_string(), cat_fact_url.into()),
+      ]),
+      ..Default::default()
+    };
+    let fact_resolver = facts::FactResolver::new(&facts_config);
+
+    // Creating an axum router and shoot a request
+    let app = app(fact_resolver, &ServerConfig::default());
+
+    // Shooting our server multiple times to make sure it will request multiple
+    // endpoints because `any` type is configured
+    for _n in 1..10 {
+      let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // Mockito server `.assert()` makes sure that the mock server was requested at least
+    // once (we added `.expect_at_least(1)` to the mock servers).
+    // We can check `assert()` for both dog and cat servers to make sure that
+    // when `any` type configured our server requested each of the API server at least
+    // once expecting that `any` returns random animal facts.
+    dog_fact_server_mock.assert();
+    cat_fact_server_mock.assert();
+  }
+
+  // Testing an error behaviour when the animal facts server endpoint is not reachable
+  // Expected behavior is returning an internal server error 500 status.
+  #[tokio::test]
+  async fn unresponsive_endpoint() {
+    // Creating facts resolver
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([(
+        "dog".to_string(),
+        "http://some-unreachable-url/".into(),
+      )]),
+      ..Default::default()
+    };
+    let fact_resolver = facts::FactResolver::new(&facts_config);
+
+    // Creating an axum router and shoot a request
+    let app = app(fact_resolver, &ServerConfig::default());
+    let response = app
+      .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+  }
+
+  /// Facts fetched through the resolver are persisted into the store and
+  /// served from it when the endpoint is not reachable
+  #[tokio::test]
+  async fn stored_fact_on_unresponsive_endpoint() {
+    // Create a mock (mockito) API endpoint server for dog facts
+    let mut server = Server::new_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["some funny dog fact".to_string()],
+      success: true,
+    };
+    server
+      .mock("GET", "/somefacts")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .create_async()
+      .await;
+
+    // Fetching the fact through the resolver with the store
+    let store = FactStore::open_in_memory().unwrap();
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([(
+        "dog".to_string(),
+        (server.url() + "/somefacts").into(),
+      )]),
+      ..Default::default()
+    };
+    let fact_resolver =
+      facts::FactResolver::new(&facts_config).with_store(store.clone());
+    fact_resolver.get_fact().await.unwrap();
+    assert_eq!(store.count("dog").unwrap(), 1, "fact is not persisted");
+
+    // Requesting the unreachable endpoint with the same store
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([(
+        "dog".to_string(),
+        "http://some-unreachable-url/".into(),
+      )]),
+      ..Default::default()
+    };
+    let fact_resolver =
+      facts::FactResolver::new(&facts_config).with_store(store);
+    let app = app(fact_resolver, &ServerConfig::default());
+    let response = app
+      .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let response_json: FactResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+      response_json.fact, dog_fact_response.facts[0],
+      "stored fact is not served"
+    );
+  }
+
+  /// Recently resolved facts are served marked as stale with the `Warning`
+  /// header when the endpoint starts failing, unless the fallback is disabled
+  #[tokio::test]
+  async fn stale_fact_on_failing_endpoint() {
+    // Create a mock (mockito) API endpoint server for dog facts
+    let mut server = Server::new_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["some funny dog fact".to_string()],
+      success: true,
+    };
+
+    for (max_staleness, stale_status) in [
+      (300, StatusCode::OK),
+      (0, StatusCode::INTERNAL_SERVER_ERROR),
+    ] {
+      let dog_fact_server_mock = server
+        .mock("GET", "/somefacts")
+        .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+        .create_async()
+        .await;
+      let facts_config = FactsConfig {
+        default: "dog".into(),
+        facts: HashMap::from([(
+          "dog".to_string(),
+          (server.url() + "/somefacts").into(),
+        )]),
+        stale: facts::config::StaleConfig {
+          max_staleness,
+          ..Default::default()
+        },
+        ..Default::default()
+      };
+      let app = app(
+        facts::FactResolver::new(&facts_config),
+        &ServerConfig::default(),
+      );
+
+      // Resolving the fresh fact while the endpoint is up
+      let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      assert!(response.headers().get(header::WARNING).is_none());
+      let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let response_json: FactResponse = serde_json::from_slice(&body).unwrap();
+      assert!(!response_json.stale, "fresh fact is marked as stale");
+
+      // Mockito responds with an error once the mock is removed
+      dog_fact_server_mock.remove_async().await;
+      let response = app
+        .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+      assert_eq!(response.status(), stale_status);
+      if stale_status == StatusCode::OK {
+        assert_eq!(response.headers()[header::WARNING], STALE_WARNING);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response_json: FactResponse =
+          serde_json::from_slice(&body).unwrap();
+        assert!(response_json.stale, "stale fact is not marked");
+        assert_eq!(response_json.fact, dog_fact_response.facts[0]);
+      }
+    }
+  }
+
+  /// Several facts are served as the distinct recent facts marked as stale
+  /// when the endpoint starts failing, fewer than requested when there are
+  /// not enough of them
+  #[tokio::test]
+  async fn distinct_stale_facts() {
+    let mut server = Server::new_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["first dog fact".to_string(), "second dog fact".to_string()],
+      success: true,
+    };
+    let dog_fact_server_mock = server
+      .mock("GET", "/somefacts")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .create_async()
+      .await;
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([(
+        "dog".to_string(),
+        (server.url() + "/somefacts").into(),
+      )]),
+      ..Default::default()
+    };
+    let fact_resolver = facts::FactResolver::new(&facts_config);
+    let fresh = fact_resolver.get_facts(None, 2, None).await.unwrap();
+    assert!(fresh.iter().all(|resolved| !resolved.stale));
+
+    dog_fact_server_mock.remove_async().await;
+    let stale = fact_resolver.get_facts(None, 3, None).await.unwrap();
+    assert!(stale.iter().all(|resolved| resolved.stale));
+    let mut facts: Vec<String> = stale
+      .into_iter()
+      .map(|resolved| resolved.fact.text)
+      .collect();
+    facts.sort();
+    assert_eq!(facts, dog_fact_response.facts);
+  }
+
+  /// Clients are identified by the session cookie, API key or client id
+  #[test]
+  fn client_identity() {
+    let headers = |pairs: &[(&'static str, &'static str)]| {
+      let mut headers = HeaderMap::new();
+      for (name, value) in pairs {
+        headers.append(*name, value.parse().unwrap());
+      }
+      headers
+    };
+
+    assert_eq!(client_id(&headers(&[])), None);
+    assert_eq!(
+      client_id(&headers(&[
+        ("cookie", "theme=dark; session=abc"),
+        ("x-client-id", "alice")
+      ])),
+      Some("session:abc".to_string())
+    );
+    assert_eq!(
+      client_id(&headers(&[
+        ("x-api-key", "secret"),
+        ("x-client-id", "alice")
+      ])),
+      Some(format!("key:{}", content_hash("secret")))
+    );
+    assert_eq!(
+      client_id(&headers(&[
+        ("cookie", "session="),
+        ("x-client-id", "alice")
+      ])),
+      Some("client:alice".to_string())
+    );
+  }
+
+  /// Facts the client has seen recently are re-fetched up to the limit and
+  /// marked as repeated when there is no other fact
+  #[tokio::test]
+  async fn repeated_fact_for_client() {
+    // Create a mock (mockito) API endpoint server always serving the same fact
+    let mut server = Server::new_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["some funny dog fact".to_string()],
+      success: true,
+    };
+    // 1 fetch for every anonymous request and the first alice and bob ones,
+    // 1 + 2 re-fetches for the second alice request
+    let dog_fact_server_mock = server
+      .mock("GET", "/somefacts")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .expect(7)
+      .create_async()
+      .await;
+
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([(
+        "dog".to_string(),
+        (server.url() + "/somefacts").into(),
+      )]),
+      dedup: facts::config::DedupConfig {
+        history: 10,
+        max_refetches: 2,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let app = app(
+      facts::FactResolver::new(&facts_config),
+      &ServerConfig::default(),
+    );
+
+    for (client, repeated) in [
+      (None, false),
+      (None, false),
+      (Some("alice"), false),
+      (Some("alice"), true),
+      (Some("bob"), false),
+    ] {
+      let mut request = Request::builder().uri("/fact");
+      if let Some(client) = client {
+        request = request.header("x-client-id", client);
+      }
+      let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let response_json: FactResponse = serde_json::from_slice(&body).unwrap();
+      assert_eq!(response_json.repeated, repeated, "{:?}", client);
+    }
+
+    dog_fact_server_mock.assert();
+  }
+
+  /// Slow requests are hedged by the request to the same or the next source
+  /// and the faster response is served, also when the first request fails
+  /// after the hedged one is sent
+  #[tokio::test]
+  async fn hedged_requests() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    // Create a mock (mockito) API endpoint server responding to the request
+    // number after the delay in milliseconds, with the facts or an invalid body
+    async fn dog_server(
+      respond: fn(usize) -> (u64, bool),
+    ) -> mockito::ServerGuard {
+      let mut server = Server::new_async().await;
+      let body = serde_json::to_string(&DogFactEndpointResponse {
+        facts: vec!["some funny dog fact".to_string()],
+        success: true,
+      })
+      .unwrap();
+      let requests = AtomicUsize::new(0);
+      server
+        .mock("GET", "/somefacts")
+        .with_chunked_body(move |writer| {
+          let (delay, valid) = respond(requests.fetch_add(1, Ordering::SeqCst));
+          std::thread::sleep(Duration::from_millis(delay));
+          match valid {
+            true => writer.write_all(body.as_bytes()),
+            false => writer.write_all(b"not a json"),
+          }
+        })
+        .create_async()
+        .await;
+      server
+    }
+
+    let slow_first = |n| (if n == 0 { 1000 } else { 0 }, true);
+    for (alternate, coalesce, servers) in [
+      (false, false, vec![dog_server(slow_first).await]),
+      (false, true, vec![dog_server(slow_first).await]),
+      (
+        true,
+        false,
+        vec![
+          dog_server(|_| (1000, true)).await,
+          dog_server(|_| (0, true)).await,
+        ],
+      ),
+      (
+        false,
+        false,
+        vec![
+          dog_server(|n| if n == 0 { (100, false) } else { (300, true) }).await,
+        ],
+      ),
+    ] {
+      let facts_config = FactsConfig {
+        default: "dog".into(),
+        facts: HashMap::from([(
+          "dog".to_string(),
+          facts::config::AnimalConfig::Sources(facts::config::SourcesConfig {
+            strategy: Default::default(),
+            sources: servers
+              .iter()
+              .map(|server| facts::config::SourceConfig {
+                url: server.url() + "/somefacts",
+                ..Default::default()
+              })
+              .collect(),
+          }),
+        )]),
+        hedge: Some(facts::config::HedgeConfig {
+          delay: Some(50),
+          alternate,
+          ..Default::default()
+        }),
+        coalesce,
+        ..Default::default()
+      };
+      let fact_resolver = facts::FactResolver::new(&facts_config);
+
+      let started = Instant::now();
+      let resolved = fact_resolver.get_fact().await.unwrap();
+      assert_eq!(resolved.fact.text, "some funny dog fact");
+      assert!(
+        started.elapsed() < Duration::from_millis(700),
+        "slow response is served, alternate: {}, coalesce: {}",
+        alternate,
+        coalesce
+      );
+      assert_eq!(fact_resolver.hedge_counts(), Some((1, 1)));
+    }
+  }
+
+  /// Concurrent requests share one endpoint request when coalescing is
+  /// configured, unless the source opts out
+  #[tokio::test]
+  async fn coalesced_requests() {
+    for (source_coalesce, expected_requests) in [(None, 1), (Some(false), 5)] {
+      // Create a mock (mockito) API endpoint server responding slowly, so the
+      // requests are in flight at the same time
+      let mut server = Server::new_async().await;
+      let body = serde_json::to_string(&DogFactEndpointResponse {
+        facts: vec!["some funny dog fact".to_string()],
+        success: true,
+      })
+      .unwrap();
+      let dog_fact_server_mock = server
+        .mock("GET", "/somefacts")
+        .with_chunked_body(move |writer| {
+          std::thread::sleep(std::time::Duration::from_millis(200));
+          writer.write_all(body.as_bytes())
+        })
+        .expect(expected_requests)
+        .create_async()
+        .await;
+
+      let facts_config = FactsConfig {
+        default: "dog".into(),
+        facts: HashMap::from([(
+          "dog".to_string(),
+          facts::config::AnimalConfig::Sources(facts::config::SourcesConfig {
+            strategy: Default::default(),
+            sources: vec![facts::config::SourceConfig {
+              url: server.url() + "/somefacts",
+              coalesce: source_coalesce,
+              ..Default::default()
+            }],
+          }),
+        )]),
+        coalesce: true,
+        ..Default::default()
+      };
+      let app = app(
+        facts::FactResolver::new(&facts_config),
+        &ServerConfig::default(),
+      );
+
+      let responses = futures::future::join_all((0..5).map(|_| {
+        app
+          .clone()
+          .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+      }))
+      .await;
+      for response in responses {
+        assert_eq!(response.unwrap().status(), StatusCode::OK);
+      }
+      dog_fact_server_mock.assert();
+    }
+  }
+
+  /// Sources configured with the generic adapters extract the facts of the
+  /// plain text, XML, CSV and HTML responses
+  #[tokio::test]
+  async fn generic_adapters() {
+    let mut server = Server::new_async().await;
+    for (path, body) in [
+      ("/text", "\n  text fact  \n"),
+      ("/xml", "<facts><fact>xml fact</fact></facts>"),
+      ("/csv", "id,fact\n1,csv fact\n"),
+      ("/html", "<p class=\"fact\">html <i>fact</i></p>"),
+    ] {
+      server
+        .mock("GET", path)
+        .with_body(body)
+        .create_async()
+        .await;
+    }
+
+    for (path, adapter, fact) in [
+      ("/text", json!({ "kind": "text" }), "text fact"),
+      (
+        "/xml",
+        json!({ "kind": "xml", "xpath": "//fact" }),
+        "xml fact",
+      ),
+      (
+        "/csv",
+        json!({ "kind": "csv", "column": "fact" }),
+        "csv fact",
+      ),
+      (
+        "/html",
+        json!({ "kind": "html", "selector": ".fact" }),
+        "html fact",
+      ),
+    ] {
+      let facts_config: FactsConfig = serde_json::from_value(json!({
+        "default": "dog",
+        "facts": {
+          "dog": { "sources": [{ "url": server.url() + path, "adapter": adapter }] }
+        }
+      }))
+      .unwrap();
+      let resolved = facts::FactResolver::new(&facts_config)
+        .get_fact()
+        .await
+        .unwrap();
+      assert_eq!(resolved.fact.text, fact, "{} adapter", path);
+    }
+  }
+
+  /// Source requests are rendered from the method, header, query and body
+  /// templates with the environment and request variables, requesting several
+  /// facts at once
+  #[tokio::test]
+  async fn templated_requests() {
+    let mut server = Server::new_async().await;
+    let dog_fact_response = |facts: &[&str]| {
+      serde_json::to_string(&DogFactEndpointResponse {
+        facts: facts.iter().map(|fact| fact.to_string()).collect(),
+        success: true,
+      })
+      .unwrap()
+    };
+    // The configured language and batch, then the requested ones
+    let mut mocks = Vec::new();
+    for (count, language, facts) in [
+      ("2", "de", &["first dog fact", "second dog fact"][..]),
+      ("3", "en", &["first", "second", "third"][..]),
+    ] {
+      let mock = server
+        .mock("POST", "/dog/facts")
+        .match_header("x-api-key", "secret-key")
+        .match_query(mockito::Matcher::AllOf(vec![
+          mockito::Matcher::UrlEncoded("number".into(), count.into()),
+          mockito::Matcher::UrlEncoded("lang".into(), language.into()),
+        ]))
+        .match_body(format!(r#"{{"animal":"dog","count":{}}}"#, count).as_str())
+        .with_body(dog_fact_response(facts))
+        .expect(1)
+        .create_async()
+        .await;
+      mocks.push(mock);
+    }
+
+    let facts_config: FactsConfig = serde_json::from_value(json!({
+      "default": "dog",
+      "language": "de",
+      "prefetch": { "batch": 2 },
+      "facts": {
+        "dog": {
+          "sources": [{
+            "url": server.url() + "/{{animal}}/facts",
+            "method": "post",
+            "headers": { "X-Api-Key": "${TEMPLATED_REQUESTS_API_KEY}" },
+            "query": { "number": "{{count}}", "lang": "{{language}}" },
+            "body": r#"{"animal":"{{animal}}","count":{{count}}}"#
+          }]
+        }
+      }
+    }))
+    .unwrap();
+    let resolver = facts::FactResolver::new(&facts_config).with_env(|name| {
+      (name == "TEMPLATED_REQUESTS_API_KEY").then(|| "secret-key".to_string())
+    });
+
+    // The configured language and batch, the second fact is prefetched
+    let resolved = resolver.get_fact().await.unwrap();
+    assert_eq!(resolved.fact.text, "first dog fact");
+    let resolved = resolver.get_facts(None, 1, Some("de")).await.unwrap();
+    assert_eq!(resolved[0].fact.text, "second dog fact");
+
+    // The requested count and language
+    let resolved = resolver.get_facts(None, 3, Some("en")).await.unwrap();
+    let texts: Vec<&str> = resolved
+      .iter()
+      .map(|resolved| resolved.fact.text.as_str())
+      .collect();
+    assert_eq!(texts, ["first", "second", "third"]);
+    for mock in mocks {
+      mock.assert();
+    }
+  }
+
+  /// OAuth2 access token is requested once for the endpoint requests and again
+  /// when the endpoint rejects it
+  #[tokio::test]
+  async fn oauth2_authenticated_requests() {
+    let mut server = Server::new_async().await;
+    let token_mock = server
+      .mock("POST", "/token")
+      .with_body(r#"{"access_token": "oauth-token", "expires_in": 3600}"#)
+      .expect(3)
+      .create_async()
+      .await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["some dog fact".to_string()],
+      success: true,
+    };
+    let dog_fact_server_mock = server
+      .mock("GET", "/dog")
+      .match_header("authorization", "Bearer oauth-token")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .expect(2)
+      .create_async()
+      .await;
+    let rejecting_mock = server
+      .mock("GET", "/revoked")
+      .with_status(401)
+      .expect(2)
+      .create_async()
+      .await;
+
+    let facts_config: FactsConfig = serde_json::from_value(json!({
+      "default": "dog",
+      "facts": {
+        "dog": {
+          "sources": [{
+            "url": server.url() + "/dog",
+            "auth": {
+              "type": "oauth2",
+              "token_url": server.url() + "/token",
+              "client_id": "client",
+              "client_secret": "secret"
+            }
+          }]
+        },
+        "revoked": {
+          "sources": [{
+            "url": server.url() + "/revoked",
+            "adapter": "dog",
+            "auth": {
+              "type": "oauth2",
+              "token_url": server.url() + "/token",
+              "client_id": "other-client",
+              "client_secret": "secret"
+            }
+          }]
+        }
+      }
+    }))
+    .unwrap();
+    let resolver = facts::FactResolver::new(&facts_config);
+    for _n in 0..2 {
+      let resolved = resolver.get_animal_fact("dog").await.unwrap();
+      assert_eq!(resolved.fact.text, "some dog fact");
+    }
+    assert!(resolver.get_animal_fact("revoked").await.is_err());
+
+    token_mock.assert();
+    dog_fact_server_mock.assert();
+    rejecting_mock.assert();
+  }
+
+  /// Endpoint responses violating the source schema are counted and respond
+  /// with the upstream contract broken error, even when there is a recent fact
+  /// of the animal
+  #[tokio::test]
+  async fn upstream_contract_broken() {
+    let mut schema = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(
+      &mut schema,
+      br#"{
+        "type": "object",
+        "required": ["facts", "success"],
+        "properties": { "facts": { "type": "array", "items": { "type": "string" } } }
+      }"#,
+    )
+    .unwrap();
+    let mut server = Server::new_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["some dog fact".to_string()],
+      success: true,
+    };
+    for path in ["/valid", "/drifted"] {
+      server
+        .mock("GET", path)
+        .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+        .expect(1)
+        .create_async()
+        .await;
+    }
+
+    let schema_path = schema.path().to_str().unwrap();
+    let facts_config: FactsConfig = serde_json::from_value(json!({
+      "default": "dog",
+      "facts": {
+        "dog": {
+          "sources": [{
+            "url": server.url() + "/drifted",
+            "schema": schema_path
+          }]
+        },
+        "valid": {
+          "sources": [{
+            "url": server.url() + "/valid",
+            "adapter": "dog",
+            "schema": schema_path
+          }]
+        }
+      }
+    }))
+    .unwrap();
+    let resolver = facts::FactResolver::new(&facts_config);
+    for animal in ["valid", "dog"] {
+      let resolved = resolver.get_animal_fact(animal).await.unwrap();
+      assert_eq!(resolved.fact.text, "some dog fact");
+    }
+    assert_eq!(resolver.schema_violations(), 0);
+
+    // The endpoint drifts, the recent dog fact does not mask the error
+    server.reset();
+    server
+      .mock("GET", "/drifted")
+      .with_body(r#"{"facts": [{"text": "some dog fact"}], "success": true}"#)
+      .create_async()
+      .await;
+    let response = app(resolver.clone(), &ServerConfig::default())
+      .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("upstream contract broken"), "{}", body);
+    assert!(body.contains("/facts/0"), "{}", body);
+    assert_eq!(resolver.schema_violations(), 1);
+  }
+
+  /// Facts are normalized by the animal filter and the responses with every
+  /// fact rejected are fetched again up to the limit, then fail the request
+  #[tokio::test]
+  async fn filtered_facts() {
+    let mut server = Server::new_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec![
+        "Some cruel dog fact".to_string(),
+        "Dogs &amp; cats\n sleep a lot".to_string(),
+      ],
+      success: true,
+    };
+    let dog_fact_server_mock = server
+      .mock("GET", "/dog")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .create_async()
+      .await;
+    let rejected_response = DogFactEndpointResponse {
+      facts: vec!["".to_string(), "Some cruel fact".to_string()],
+      success: true,
+    };
+    let rejected_mock = server
+      .mock("GET", "/rejected")
+      .with_body(serde_json::to_string(&rejected_response).unwrap())
+      .expect(3)
+      .create_async()
+      .await;
+
+    let facts_config: FactsConfig = serde_json::from_value(json!({
+      "default": "dog",
+      "facts": {
+        "dog": server.url() + "/dog",
+        "rejected": {
+          "sources": [{ "url": server.url() + "/rejected", "adapter": "dog" }]
+        }
+      },
+      "filters": {
+        "dog": { "banned_words": ["cruel"] },
+        "rejected": { "banned_words": ["cruel"], "max_refetches": 2 }
+      }
+    }))
+    .unwrap();
+    let resolver = facts::FactResolver::new(&facts_config);
+    let resolved = resolver.get_animal_fact("dog").await.unwrap();
+    assert_eq!(resolved.fact.text, "Dogs & cats sleep a lot");
+    let err = resolver.get_animal_fact("rejected").await.unwrap_err();
+    assert!(err.downcast_ref::<facts::filter::FactsRejected>().is_some());
+
+    dog_fact_server_mock.assert();
+    rejected_mock.assert();
+
+    // Every dog fact is rejected now, the recent dog fact does not mask it
+    server.reset();
+    let rejected_mock = server
+      .mock("GET", "/dog")
+      .with_body(serde_json::to_string(&rejected_response).unwrap())
+      .expect(4)
+      .create_async()
+      .await;
+    let response = app(resolver, &ServerConfig::default())
+      .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    rejected_mock.assert();
+
+    let invalid: Result<FactsConfig, _> = serde_json::from_value(json!({
+      "default": "dog",
+      "facts": { "dog": server.url() + "/dog" },
+      "filters": { "dog": { "deny": ["(unclosed"] } }
+    }));
+    assert!(invalid.is_err());
+  }
+
+  /// The animal with multiple sources is still served when one of the sources
+  /// is down, whatever the strategy is
+  #[tokio::test]
+  async fn failover_between_sources() {
+    // Create a mock (mockito) API endpoint server serving the cat facts in
+    // the dog facts response format
+    let mut server = Server::new_async().await;
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["some funny cat fact".to_string()],
+      success: true,
+    };
+    let dog_format_mock = server
+      .mock("GET", "/dogformat")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .expect(10)
+      .create_async()
+      .await;
+
+    for strategy in ["failover", "round_robin", "weighted_random", "fastest"] {
+      let facts_config: FactsConfig = serde_json::from_value(json!({
+        "default": "cat",
+        "facts": {
+          "cat": {
+            "strategy": strategy,
+            "sources": [
+              { "url": "http://some-unreachable-url/" },
+              {
+                "url": server.url() + "/dogformat",
+                "adapter": "dog",
+                "weight": 3
+              }
+            ]
+          }
+        }
+      }))
+      .unwrap();
+
+      // Zero staleness makes sure the facts are not served from the recent ones
+      let facts_config = FactsConfig {
+        stale: facts::config::StaleConfig {
+          max_staleness: 0,
+          ..Default::default()
+        },
+        ..facts_config
+      };
+      let app = app(
+        facts::FactResolver::new(&facts_config),
+        &ServerConfig::default(),
+      );
+      let requests = if strategy == "round_robin" { 4 } else { 2 };
+      for _n in 0..requests {
+        let response = app
+          .clone()
+          .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+          .await
+          .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", strategy);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response_json: FactResponse =
+          serde_json::from_slice(&body).unwrap();
+        assert_eq!(response_json.fact, dog_fact_response.facts[0]);
+        assert!(!response_json.stale);
+      }
+    }
+
+    dog_format_mock.assert();
+  }
+
+  /// Animals configured with the `file://` URL are served from the local
+  /// corpus files in the same response format
+  #[tokio::test]
+  async fn offline_corpus() {
+    // Loading the bundled offline configuration
+    let config_data = crate::config::ConfigData::new("config.offline.json")
+      .expect("offline configuration loading error");
+    let fact_resolver = facts::FactResolver::new(&config_data.animals);
+
+    let app = app(fact_resolver, &config_data.server);
+    for _n in 1..10 {
+      let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/fact").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+      let status = response.status();
+      let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      assert_eq!(status, StatusCode::OK, "{:?}", body);
+      let response_json: FactResponse = serde_json::from_slice(&body).unwrap();
+      assert!(!response_json.fact.is_empty(), "fact is empty");
+      assert!(
+        ["dog", "cat"].contains(&response_json.animal.as_str()),
+        "animal type is not as expected"
+      );
+    }
+  }
+
+  // Testing by spawning a real server that listens for requests at the address.
+  // This is an integration test where we are spawning a real server and requesting
+  // it by the `reqwest` client.
+  #[tokio::test]
+  async fn listening_on_the_address() {
+    // Create a mock (mockito) API endpoint server for dog facts
+    let mut server = Server::new_async().await;
+    let dog_fact_url = server.url() + "/somefacts";
+    let dog_fact_response = DogFactEndpointResponse {
+      facts: vec!["some funny dog fact".to_string()],
+      success: true,
+    };
+    let dog_fact_server_mock = server
+      .mock("GET", "/somefacts")
+      .with_body(serde_json::to_string(&dog_fact_response).unwrap())
+      .create_async()
+      .await;
+
+    // Creating facts resolver
+    let facts_config = FactsConfig {
+      default: "dog".into(),
+      facts: HashMap::from([("dog".to_string(), dog_fact_url.into())]),
+      ..Default::default()
+    };
+    let fact_resolver = facts::FactResolver::new(&facts_config);
+
+    // Creating an axum server and spawn a tokio task
+    let listening_address = "0.0.0.0:0"; // any available address and port
+    let listener = TcpListener::bind(listening_address).unwrap();
+    let current_addr = listener.local_addr().unwrap();
+    let app = app(fact_resolver, &ServerConfig::default());
+    tokio::spawn(async move {
+      axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+    });
+
+    // Making request to the server by the `reqwest`
+    let response = reqwest::get(format!("http://{}/fact", current_addr))
+      .await
+      .unwrap();
+
+    // Checking assertions
+    assert_eq!(
+      response.status(),
+      StatusCode::OK,
+      "server response status code is not OK"
+    );
+
+    let response_json: FactResponse =
+      serde_json::from_str(&response.text().await.unwrap()).unwrap();
+
+    assert_eq!(
+      response_json.animal, "dog",
+      "animal type is not as expected"
+    );
+    assert_eq!(
+      response_json.fact, dog_fact_response.facts[0],
+      "facts are different"
+    );
+
+    dog_fact_server_mock.assert();
+  }
+}
@@ -0,0 +1,284 @@
+//! OpenAPI specification of the server routes.
+//!
+//! The specification is generated from the `utoipa::path` annotations of the
+//! handlers and the response types and served at `/openapi.json`. The Swagger
+//! UI documentation page is served at `/docs` when enabled in the configuration.
+
+use super::AppState;
+use axum::{
+  extract::Path,
+  http::{header, StatusCode},
+  response::{IntoResponse, Json, Redirect, Response},
+  routing::get,
+  Router,
+};
+use std::sync::Arc;
+use tracing::{error, trace};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::Config;
+
+/// Server OpenAPI specification
+#[derive(OpenApi)]
+#[openapi(
+  info(title = "Animal facts API"),
+  paths(
+    super::facts_handler,
+    super::daily::daily_fact_handler,
+    super::stream::fact_stream_handler,
+    super::search::search_handler
+  ),
+  components(schemas(
+    super::FactResponse,
+    super::search::SearchResponse,
+    super::search::SearchResult
+  ))
+)]
+pub struct ApiDoc;
+
+/// Handler for the `/openapi.json` route
+pub async fn openapi_handler() -> Json<utoipa::openapi::OpenApi> {
+  trace!("serving the openapi specification");
+  Json(ApiDoc::openapi())
+}
+
+/// Router serving the bundled Swagger UI at `/docs`
+pub fn docs_router() -> Router<AppState> {
+  Router::new()
+    .route("/docs", get(|| async { Redirect::permanent("/docs/") }))
+    .route("/docs/", get(|| docs_file(String::new())))
+    .route("/docs/*path", get(|Path(path)| docs_file(path)))
+}
+
+/// Serving the Swagger UI static file configured to use our specification
+async fn docs_file(path: String) -> Response {
+  trace!("serving the docs file: {}", path);
+  let config = Arc::new(Config::from("/openapi.json"));
+  match utoipa_swagger_ui::serve(&path, config) {
+    Ok(Some(file)) => (
+      [(header::CONTENT_TYPE, file.content_type)],
+      file.bytes.into_owned(),
+    )
+      .into_response(),
+    Ok(None) => StatusCode::NOT_FOUND.into_response(),
+    Err(err) => {
+      error!("failed to serve the docs file {}: {}", path, err);
+      (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+    }
+  }
+}
+
+/// Testing the specification against the actual handlers behaviour, so the
+/// tests fail when the specification drifts from the handlers.
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::ServerConfig;
+  use crate::facts::FactResolver;
+  use crate::handlers::app;
+  use crate::test_util::{dog_config, dog_resolver};
+  use axum::{body::Body, http::Request};
+  use mockito::Server;
+  use serde_json::Value;
+  use tower::ServiceExt;
+
+  /// Requesting the app and returning the status, content type and body
+  async fn request(app: &Router, uri: &str) -> (StatusCode, String, Vec<u8>) {
+    let response = app
+      .clone()
+      .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+    let status = response.status();
+    let content_type = response.headers()[header::CONTENT_TYPE]
+      .to_str()
+      .unwrap()
+      .to_string();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, content_type, body.to_vec())
+  }
+
+  /// Getting the specification from the `/openapi.json` route
+  async fn served_spec(app: &Router) -> Value {
+    let (status, _, body) = request(app, "/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+  }
+
+  /// Asserting the response is documented for the path with the same status
+  /// and content type
+  fn assert_documented(
+    spec: &Value,
+    path: &str,
+    status: StatusCode,
+    content_type: &str,
+  ) -> Value {
+    let response = &spec["paths"][path]["get"]["responses"][status.as_str()];
+    assert!(
+      response.is_object(),
+      "{} response of {} is not documented",
+      status,
+      path
+    );
+    let documented = response["content"]
+      .as_object()
+      .unwrap()
+      .keys()
+      .find(|documented| content_type.starts_with(documented.as_str()))
+      .unwrap_or_else(|| {
+        panic!("{} content of {} is not documented", content_type, path)
+      });
+    response["content"][documented]["schema"].clone()
+  }
+
+  /// Resolving the `$ref` of the schema in the specification components
+  fn resolve<'a>(spec: &'a Value, schema: &'a Value) -> &'a Value {
+    match schema["$ref"].as_str() {
+      Some(reference) => {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        &spec["components"]["schemas"][name]
+      }
+      None => schema,
+    }
+  }
+
+  /// JSON schema type name of the value
+  fn json_type(value: &Value) -> &'static str {
+    match value {
+      Value::Null => "null",
+      Value::Bool(_) => "boolean",
+      Value::Number(number) if number.is_f64() => "number",
+      Value::Number(_) => "integer",
+      Value::String(_) => "string",
+      Value::Array(_) => "array",
+      Value::Object(_) => "object",
+    }
+  }
+
+  /// Creating the app with the unreachable dog facts endpoint
+  fn unreachable_app() -> Router {
+    let facts_config = dog_config("http://some-unreachable-url/");
+    app(FactResolver::new(&facts_config), &ServerConfig::default())
+  }
+
+  /// The successful `/fact` response should match the documented schema
+  #[tokio::test]
+  async fn fact_response_matches_spec() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, _mock) = dog_resolver(&mut server, None).await;
+    let app = app(fact_resolver, &ServerConfig::default());
+    let spec = served_spec(&app).await;
+
+    let (status, content_type, body) =
+      request(&app, "/fact?verbose=true").await;
+    let schema = assert_documented(&spec, "/fact", status, &content_type);
+    let schema = resolve(&spec, &schema);
+    let actual: Value = serde_json::from_slice(&body).unwrap();
+
+    // Optional metadata fields are omitted when the endpoint does not provide
+    // them, so every actual field should be documented
+    let properties = schema["properties"].as_object().unwrap();
+    let actual = actual.as_object().unwrap();
+    for (name, value) in actual {
+      let property = properties
+        .get(name)
+        .unwrap_or_else(|| panic!("field {} is not documented", name));
+      let documented: Vec<&str> = match &property["type"] {
+        Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+        documented => documented.as_str().into_iter().collect(),
+      };
+      assert!(
+        documented.contains(&json_type(value)),
+        "documented and actual types of {} are different",
+        name
+      );
+    }
+    for required in schema["required"].as_array().unwrap() {
+      assert!(
+        actual.contains_key(required.as_str().unwrap()),
+        "required field {} is missing",
+        required
+      );
+    }
+  }
+
+  /// The `/fact` error response should match the documented one
+  #[tokio::test]
+  async fn fact_error_matches_spec() {
+    let app = unreachable_app();
+    let spec = served_spec(&app).await;
+
+    let (status, content_type, _) = request(&app, "/fact").await;
+    let schema = assert_documented(&spec, "/fact", status, &content_type);
+
+    assert_eq!(schema["type"], "string", "error is not documented as text");
+  }
+
+  /// Every documented path should be routed and every documented query
+  /// parameter should be accepted
+  #[tokio::test]
+  async fn documented_paths_are_routed() {
+    let app = unreachable_app();
+    let spec = served_spec(&app).await;
+
+    let (status, content_type, _) =
+      request(&app, "/fact/stream?animal=parrot&interval=1").await;
+    assert_documented(&spec, "/fact/stream", status, &content_type);
+
+    let parameters = spec["paths"]["/fact/stream"]["get"]["parameters"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .filter(|parameter| parameter["in"] == "query")
+      .map(|parameter| parameter["name"].as_str().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(parameters, vec!["animal", "interval"]);
+
+    for path in spec["paths"].as_object().unwrap().keys() {
+      let response = app
+        .clone()
+        .oneshot(
+          Request::builder()
+            .method("HEAD")
+            .uri(path.as_str())
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_ne!(
+        response.status(),
+        StatusCode::NOT_FOUND,
+        "documented path {} is not routed",
+        path
+      );
+    }
+  }
+
+  /// Docs page is served only when enabled in the configuration
+  #[tokio::test]
+  async fn docs_page() {
+    let facts_config = dog_config("http://some-unreachable-url/");
+    let fact_resolver = FactResolver::new(&facts_config);
+
+    let disabled = app(fact_resolver.clone(), &ServerConfig::default());
+    let response = disabled
+      .oneshot(
+        Request::builder()
+          .uri("/docs/")
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let server_config = ServerConfig {
+      docs: true,
+      ..Default::default()
+    };
+    let enabled = app(fact_resolver, &server_config);
+    let (status, content_type, _) = request(&enabled, "/docs/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(content_type.starts_with("text/html"));
+  }
+}
@@ -0,0 +1,230 @@
+//! Full-text fact search for the `/facts/search` route.
+//!
+//! Searching all the facts fetched through the `FactResolver`, ranked by the
+//! relevance with the matched terms highlighted. Results are paged by the opaque
+//! `next_cursor` passed as the `cursor` parameter of the next request.
+
+use crate::facts::{store::SearchCursor, FactResolver};
+use axum::{
+  extract::{Query, State},
+  http::StatusCode,
+  response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, trace};
+use utoipa::{IntoParams, ToSchema};
+
+/// Number of the results returned when the limit is omitted
+const DEFAULT_LIMIT: usize = 10;
+
+/// Maximum number of the results of a single request
+const MAX_LIMIT: usize = 100;
+
+/// Query parameters of the `/facts/search` route
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+  /// Search terms, all of them should match
+  q: String,
+  /// Animal type of the facts, all the animals when omitted
+  animal: Option<String>,
+  /// Number of the results, 10 by default and 100 at most
+  limit: Option<usize>,
+  /// `next_cursor` of the previous page
+  cursor: Option<String>,
+}
+
+/// Fact matching the search terms
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SearchResult {
+  /// Fact text
+  fact: String,
+  /// Animal type the fact is about
+  animal: String,
+  /// Fact text part with the matched terms wrapped in `<mark>` tags
+  snippet: String,
+  /// Relevance score, the higher the more relevant
+  score: f64,
+}
+
+/// Page of the search results
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SearchResponse {
+  /// Results ordered by the relevance
+  results: Vec<SearchResult>,
+  /// Cursor of the next page, omitted on the last page
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<String>,
+}
+
+/// Handler for the `/facts/search` route. Responds with the page of the
+/// results or `400 Bad Request` for the empty query or invalid cursor.
+#[utoipa::path(
+  get,
+  path = "/facts/search",
+  params(SearchParams),
+  responses(
+    (status = 200, description = "Facts matching the search terms", body = SearchResponse),
+    (status = 400, description = "Empty search query or invalid cursor", body = String, content_type = "text/plain"),
+    (status = 500, description = "Search error", body = String, content_type = "text/plain")
+  )
+)]
+#[instrument(skip(fact_resolver))]
+pub async fn search_handler(
+  State(fact_resolver): State<FactResolver>,
+  Query(params): Query<SearchParams>,
+) -> Response {
+  trace!("handling the fact search request");
+  if params.q.trim().is_empty() {
+    return (StatusCode::BAD_REQUEST, "empty search query").into_response();
+  }
+  let cursor = match params
+    .cursor
+    .as_deref()
+    .map(str::parse::<SearchCursor>)
+    .transpose()
+  {
+    Ok(cursor) => cursor,
+    Err(err) => {
+      return (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+    }
+  };
+  let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+  // Requesting one more hit to know whether there is a next page
+  let search = fact_resolver.search(
+    &params.q,
+    params.animal.as_deref(),
+    cursor,
+    limit + 1,
+  );
+  match search.await {
+    Ok(mut hits) => {
+      let next_cursor = if hits.len() > limit {
+        hits.truncate(limit);
+        hits.last().map(|hit| hit.cursor.to_string())
+      } else {
+        None
+      };
+      let results = hits
+        .into_iter()
+        .map(|hit| SearchResult {
+          fact: hit.text,
+          animal: hit.animal,
+          snippet: hit.snippet,
+          // BM25 scores of SQLite are negative, the lower the more relevant
+          score: -hit.score,
+        })
+        .collect();
+      Json(SearchResponse {
+        results,
+        next_cursor,
+      })
+      .into_response()
+    }
+    Err(err) => {
+      (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::ServerConfig;
+  use crate::facts::adapters::cat::CatFactEndpointResponse;
+  use crate::facts::config::FactsConfig;
+  use crate::facts::store::FactStore;
+  use crate::handlers::app;
+  use axum::{body::Body, http::Request, Router};
+  use mockito::Server;
+  use std::collections::HashMap;
+  use tower::ServiceExt;
+
+  /// Requesting the route, returning the status and body
+  async fn request(app: &Router, uri: &str) -> (StatusCode, String) {
+    let response = app
+      .clone()
+      .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+  }
+
+  /// Fetched facts are searchable and paged by the cursor
+  #[tokio::test]
+  async fn search_fetched_facts() {
+    // Create a mock (mockito) API endpoint server for cat facts
+    let mut server = Server::new_async().await;
+    for (path, text) in [
+      ("/first", "Cats purr when they are happy"),
+      ("/second", "Happy cats knead with their paws"),
+      ("/third", "Cats sleep most of the day"),
+    ] {
+      let cat_fact_response = CatFactEndpointResponse {
+        text: text.into(),
+        animal_type: "cat".into(),
+        deleted: false,
+        ..Default::default()
+      };
+      server
+        .mock("GET", path)
+        .with_body(serde_json::to_string(&cat_fact_response).unwrap())
+        .create_async()
+        .await;
+    }
+
+    // Fetching every fact through the resolvers sharing the store
+    let store = FactStore::open_in_memory().unwrap();
+    for path in ["/first", "/second", "/third"] {
+      let facts_config = FactsConfig {
+        default: "cat".into(),
+        facts: HashMap::from([(
+          "cat".to_string(),
+          (server.url() + path).into(),
+        )]),
+        ..Default::default()
+      };
+      FactResolver::new(&facts_config)
+        .with_store(store.clone())
+        .get_fact()
+        .await
+        .unwrap();
+    }
+    let facts_config = FactsConfig {
+      default: "cat".into(),
+      facts: HashMap::from([("cat".to_string(), server.url().into())]),
+      ..Default::default()
+    };
+    let app = app(
+      FactResolver::new(&facts_config).with_store(store),
+      &ServerConfig::default(),
+    );
+
+    let (status, body) = request(&app, "/facts/search?q=happy&limit=1").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let first_page: SearchResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(first_page.results.len(), 1);
+    assert!(first_page.results[0].snippet.contains("<mark>"));
+    let cursor = first_page.next_cursor.expect("no next page");
+
+    let uri = format!("/facts/search?q=happy&limit=1&cursor={}", cursor);
+    let (_, body) = request(&app, &uri).await;
+    let second_page: SearchResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(second_page.results.len(), 1);
+    assert!(second_page.next_cursor.is_none(), "unexpected next page");
+    assert_ne!(first_page.results[0].fact, second_page.results[0].fact);
+    assert!(first_page.results[0].score >= second_page.results[0].score);
+
+    let (_, body) = request(&app, "/facts/search?q=sleep&animal=dog").await;
+    let response: SearchResponse = serde_json::from_str(&body).unwrap();
+    assert!(response.results.is_empty());
+
+    for uri in ["/facts/search?q=%20", "/facts/search?q=cats&cursor=zz"] {
+      let (status, _) = request(&app, uri).await;
+      assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+  }
+}
@@ -0,0 +1,280 @@
+//! Server-Sent Events fact stream for the `/fact/stream` route.
+//!
+//! Every client gets its own stream that resolves a new fact through the
+//! `FactResolver` on each interval tick. Events are numbered, so a reconnecting
+//! client sending the `Last-Event-ID` header continues the numbering where it
+//! stopped. The stream is dropped by axum when the client disconnects.
+
+use super::{client_id, FactResponse};
+use crate::config::{ServerConfig, StreamConfig};
+use crate::facts::FactResolver;
+use axum::{
+  extract::{Query, State},
+  http::{HeaderMap, StatusCode},
+  response::{
+    sse::{Event, KeepAlive, Sse},
+    IntoResponse, Response,
+  },
+};
+use futures::stream;
+use serde::Deserialize;
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use tracing::{instrument, trace};
+use utoipa::IntoParams;
+
+/// Header name used by the `EventSource` clients when reconnecting
+const LAST_EVENT_ID: &str = "last-event-id";
+
+/// Query parameters of the `/fact/stream` route
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreamParams {
+  /// Animal type to stream, the configured default is used when omitted
+  animal: Option<String>,
+  /// Interval between the facts in seconds
+  interval: Option<u64>,
+}
+
+/// Per-client state of the fact stream
+struct FactStream {
+  fact_resolver: FactResolver,
+  animal: Option<String>,
+  /// Client identity suppressing the facts it has seen recently
+  client: Option<String>,
+  ticker: Interval,
+  next_id: u64,
+}
+
+impl FactStream {
+  /// Waiting for the next tick and resolving the fact into an SSE event.
+  /// Resolver errors are sent as `error` events without closing the stream.
+  async fn next_event(&mut self) -> Event {
+    self.ticker.tick().await;
+    let id = self.next_id;
+    self.next_id += 1;
+
+    let result = match (&self.client, &self.animal) {
+      (Some(client), animal) => {
+        self
+          .fact_resolver
+          .get_client_fact(animal.as_deref(), client)
+          .await
+      }
+      (None, Some(animal)) => self.fact_resolver.get_animal_fact(animal).await,
+      (None, None) => self.fact_resolver.get_fact().await,
+    };
+    let event = Event::default().id(id.to_string());
+    match result {
+      Ok(resolved) => event
+        .event("fact")
+        .json_data(FactResponse::from(resolved))
+        .expect("fact response serialization error"),
+      Err(err) => event.event("error").data(err.to_string()),
+    }
+  }
+}
+
+impl Drop for FactStream {
+  fn drop(&mut self) {
+    trace!("fact stream closed after {} events", self.next_id - 1);
+  }
+}
+
+/// Clamping the requested interval into the configured bounds, but not below
+/// a second, as the ticker panics on the zero interval
+pub(crate) fn stream_interval(
+  config: &StreamConfig,
+  requested: Option<u64>,
+) -> Duration {
+  let seconds = requested.unwrap_or(config.default_interval).clamp(
+    config.min_interval,
+    config.max_interval.max(config.min_interval),
+  );
+  Duration::from_secs(seconds.max(1))
+}
+
+/// Handler for the `/fact/stream` route. Responds with the `text/event-stream`
+/// of facts or `400 Bad Request` when the animal is not configured.
+#[utoipa::path(
+  get,
+  path = "/fact/stream",
+  params(
+    StreamParams,
+    ("Last-Event-ID" = Option<u64>, Header, description = "Id of the last event received before reconnecting")
+  ),
+  responses(
+    (status = 200, description = "Stream of `fact` events with `FactResponse` data", body = String, content_type = "text/event-stream"),
+    (status = 400, description = "Unknown animal type", body = String, content_type = "text/plain")
+  )
+)]
+#[instrument(skip(fact_resolver, server_config, headers))]
+pub async fn fact_stream_handler(
+  State(fact_resolver): State<FactResolver>,
+  State(server_config): State<Arc<ServerConfig>>,
+  Query(params): Query<StreamParams>,
+  headers: HeaderMap,
+) -> Response {
+  trace!("handling the fact stream request");
+  if let Some(animal) = &params.animal {
+    if !fact_resolver.animals().contains(animal) {
+      return (
+        StatusCode::BAD_REQUEST,
+        format!("unknown animal type: {}", animal),
+      )
+        .into_response();
+    }
+  }
+
+  let last_event_id = headers
+    .get(LAST_EVENT_ID)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.trim().parse::<u64>().ok())
+    .unwrap_or(0);
+
+  let config = &server_config.stream;
+  let mut ticker = interval(stream_interval(config, params.interval));
+  ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+  let fact_stream = FactStream {
+    fact_resolver,
+    animal: params.animal,
+    client: client_id(&headers),
+    ticker,
+    next_id: last_event_id.saturating_add(1),
+  };
+  let events = stream::unfold(fact_stream, |mut fact_stream| async move {
+    let event = fact_stream.next_event().await;
+    Some((Ok::<_, Infallible>(event), fact_stream))
+  });
+
+  Sse::new(events)
+    .keep_alive(
+      KeepAlive::new()
+        .interval(Duration::from_secs(config.keep_alive.max(1)))
+        .text("keep-alive"),
+    )
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::handlers::app;
+  use crate::test_util::{dog_resolver, DOG_FACT};
+  use axum::{body::Body, http::Request};
+  use hyper::body::HttpBody;
+  use mockito::Server;
+  use tower::ServiceExt;
+
+  /// The first event should be sent immediately and contain the fact
+  #[tokio::test]
+  async fn first_event_contains_fact() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, _mock) = dog_resolver(&mut server, None).await;
+    let app = app(fact_resolver, &ServerConfig::default());
+
+    let response = app
+      .oneshot(
+        Request::builder()
+          .uri("/fact/stream?animal=dog&interval=1")
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+      response.headers()["content-type"],
+      "text/event-stream",
+      "response is not an event stream"
+    );
+
+    let mut body = response.into_body();
+    let chunk = body.data().await.unwrap().unwrap();
+    let event = String::from_utf8(chunk.to_vec()).unwrap();
+
+    assert!(event.contains("event:fact"), "unexpected event: {}", event);
+    assert!(event.contains("id:1\n"), "unexpected event id: {}", event);
+    assert!(
+      event.contains(DOG_FACT),
+      "event does not contain the fact: {}",
+      event
+    );
+  }
+
+  /// Reconnecting client should continue the events numbering
+  #[tokio::test]
+  async fn last_event_id_continues_numbering() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, _mock) = dog_resolver(&mut server, None).await;
+    let app = app(fact_resolver, &ServerConfig::default());
+
+    let response = app
+      .oneshot(
+        Request::builder()
+          .uri("/fact/stream")
+          .header("Last-Event-ID", "41")
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+
+    let mut body = response.into_body();
+    let chunk = body.data().await.unwrap().unwrap();
+    let event = String::from_utf8(chunk.to_vec()).unwrap();
+
+    assert!(event.contains("id:42\n"), "unexpected event id: {}", event);
+  }
+
+  /// Requesting an animal that is not in the configuration
+  #[tokio::test]
+  async fn unknown_animal() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, _mock) = dog_resolver(&mut server, None).await;
+    let app = app(fact_resolver, &ServerConfig::default());
+
+    let response = app
+      .oneshot(
+        Request::builder()
+          .uri("/fact/stream?animal=parrot")
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  }
+
+  /// Requested intervals should be clamped into the configured bounds
+  #[test]
+  fn interval_bounds() {
+    let config = StreamConfig {
+      min_interval: 5,
+      max_interval: 60,
+      default_interval: 10,
+      keep_alive: 15,
+    };
+
+    assert_eq!(stream_interval(&config, None), Duration::from_secs(10));
+    assert_eq!(stream_interval(&config, Some(1)), Duration::from_secs(5));
+    assert_eq!(stream_interval(&config, Some(600)), Duration::from_secs(60));
+  }
+
+  /// The zero interval should be raised to a second instead of panicking
+  #[tokio::test]
+  async fn zero_interval() {
+    let config = StreamConfig {
+      min_interval: 0,
+      default_interval: 0,
+      ..Default::default()
+    };
+
+    let zero_interval = stream_interval(&config, Some(0));
+    assert_eq!(zero_interval, Duration::from_secs(1));
+    assert_eq!(stream_interval(&config, None), Duration::from_secs(1));
+    interval(zero_interval).tick().await;
+  }
+}
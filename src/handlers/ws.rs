@@ -0,0 +1,585 @@
+//! WebSocket fact subscriptions for the `/ws` route.
+//!
+//! Clients send JSON commands to `get` a single fact, `subscribe` to animals
+//! with an interval or `unsubscribe` from them, and receive the facts as JSON
+//! messages. Subscriptions are served by the shared `FactHub`, so all the
+//! subscribers of the same animal and interval share one upstream request per
+//! tick instead of requesting the endpoint on their own.
+
+use super::{stream::stream_interval, FactResponse};
+use crate::config::ServerConfig;
+use crate::facts::{FactResolver, ResolvedFact};
+use axum::{
+  extract::{
+    ws::{Message, WebSocket, WebSocketUpgrade},
+    State,
+  },
+  response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  future,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+use tokio::{
+  sync::{broadcast, mpsc, Semaphore},
+  task::JoinHandle,
+  time::{interval, interval_at, sleep_until, Instant, MissedTickBehavior},
+};
+use tracing::{instrument, trace};
+
+/// Number of messages buffered for the slow subscribers of the shared channel
+const FAN_OUT_CAPACITY: usize = 16;
+
+/// Number of messages buffered for sending to a single connection
+const OUTGOING_CAPACITY: usize = 32;
+
+/// Commands sent by the client
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClientCommand {
+  /// Subscribe to the animals facts with the interval in seconds
+  Subscribe {
+    animals: Vec<String>,
+    interval: Option<u64>,
+  },
+  /// Get a single fact, the configured default animal is used when omitted
+  Get { animal: Option<String> },
+  /// Unsubscribe from the animals, or from all of them when empty
+  Unsubscribe {
+    #[serde(default)]
+    animals: Vec<String>,
+  },
+}
+
+/// Messages sent to the client
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ServerMessage {
+  Fact(FactResponse),
+  Subscribed { animals: Vec<String>, interval: u64 },
+  Unsubscribed { animals: Vec<String> },
+  Error { message: String },
+}
+
+impl From<anyhow::Result<ResolvedFact>> for ServerMessage {
+  fn from(result: anyhow::Result<ResolvedFact>) -> Self {
+    match result {
+      Ok(resolved) => ServerMessage::Fact(resolved.into()),
+      Err(err) => ServerMessage::Error {
+        message: err.to_string(),
+      },
+    }
+  }
+}
+
+/// Fan-out channels keyed by the animal and the interval in seconds
+type Channels = HashMap<(String, u64), broadcast::Sender<ServerMessage>>;
+
+/// Shared fan-out of the subscribed facts. Every `(animal, interval)` pair has
+/// one background task requesting the facts and broadcasting them to all the
+/// subscribers. The task stops once the last subscriber is gone.
+#[derive(Clone)]
+pub struct FactHub {
+  fact_resolver: FactResolver,
+  channels: Arc<Mutex<Channels>>,
+}
+
+impl FactHub {
+  pub fn new(fact_resolver: FactResolver) -> Self {
+    FactHub {
+      fact_resolver,
+      channels: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+
+  /// Subscribing to the facts of the animal, spawning the fan-out task if
+  /// there is no one for the animal and interval yet
+  pub fn subscribe(
+    &self,
+    animal: &str,
+    interval: Duration,
+  ) -> broadcast::Receiver<ServerMessage> {
+    let key = (animal.to_string(), interval.as_secs());
+    let mut channels = self.channels.lock().unwrap();
+    if let Some(sender) = channels.get(&key) {
+      return sender.subscribe();
+    }
+
+    trace!("starting fact fan-out for {:?}", key);
+    let (sender, receiver) = broadcast::channel(FAN_OUT_CAPACITY);
+    channels.insert(key.clone(), sender.clone());
+    tokio::spawn(self.clone().fan_out(key, sender));
+    receiver
+  }
+
+  /// Requesting the fact on every tick and broadcasting it to the subscribers
+  async fn fan_out(
+    self,
+    key: (String, u64),
+    sender: broadcast::Sender<ServerMessage>,
+  ) {
+    let mut ticker = interval(Duration::from_secs(key.1));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+      ticker.tick().await;
+      {
+        // Checking under the lock, so no one can subscribe in between
+        let mut channels = self.channels.lock().unwrap();
+        if sender.receiver_count() == 0 {
+          trace!("stopping fact fan-out for {:?}", key);
+          channels.remove(&key);
+          return;
+        }
+      }
+      let result = self.fact_resolver.get_animal_fact(&key.0).await;
+      // Sending fails only when all the subscribers are gone in the meantime
+      let _ = sender.send(result.into());
+    }
+  }
+}
+
+/// Handler for the `/ws` route upgrading the connection to the WebSocket
+#[instrument(skip_all)]
+pub async fn ws_handler(
+  ws: WebSocketUpgrade,
+  State(fact_resolver): State<FactResolver>,
+  State(server_config): State<Arc<ServerConfig>>,
+  State(fact_hub): State<FactHub>,
+) -> Response {
+  trace!("upgrading the connection to websocket");
+  ws.on_upgrade(move |socket| {
+    let (outgoing, outgoing_rx) = mpsc::channel(OUTGOING_CAPACITY);
+    let max_pending_gets = server_config.websocket.max_pending_gets;
+    let connection = Connection {
+      pending_gets: Arc::new(Semaphore::new(max_pending_gets)),
+      fact_resolver,
+      fact_hub,
+      server_config,
+      subscriptions: HashMap::new(),
+      outgoing,
+    };
+    connection.run(socket, outgoing_rx)
+  })
+}
+
+/// State of a single WebSocket connection
+struct Connection {
+  fact_resolver: FactResolver,
+  fact_hub: FactHub,
+  server_config: Arc<ServerConfig>,
+  /// Forwarding tasks of the subscribed animals
+  subscriptions: HashMap<String, JoinHandle<()>>,
+  /// Messages queued for sending to the client
+  outgoing: mpsc::Sender<ServerMessage>,
+  /// Permits of the `get` commands fetching the facts at the same time
+  pending_gets: Arc<Semaphore>,
+}
+
+impl Connection {
+  /// Serving the connection until the client closes it or stops answering
+  /// the pings
+  async fn run(
+    mut self,
+    mut socket: WebSocket,
+    mut outgoing_rx: mpsc::Receiver<ServerMessage>,
+  ) {
+    let config = &self.server_config.websocket;
+    let ping_interval = Duration::from_secs(config.ping_interval.max(1));
+    let pong_timeout = Duration::from_secs(config.pong_timeout);
+    let mut ping = interval_at(Instant::now() + ping_interval, ping_interval);
+    // Deadline of the pong answering the oldest unanswered ping
+    let mut pong_deadline: Option<Instant> = None;
+
+    loop {
+      tokio::select! {
+        incoming = socket.recv() => match incoming {
+          Some(Ok(Message::Text(text))) => {
+            if let Some(reply) = self.handle_command(&text) {
+              if send(&mut socket, &reply).await.is_err() {
+                break;
+              }
+            }
+          }
+          Some(Ok(Message::Pong(_))) => pong_deadline = None,
+          Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+          Some(Ok(_)) => {}
+        },
+        Some(message) = outgoing_rx.recv() => {
+          if send(&mut socket, &message).await.is_err() {
+            break;
+          }
+        }
+        _ = ping.tick() => {
+          if socket.send(Message::Ping(Vec::new())).await.is_err() {
+            break;
+          }
+          pong_deadline.get_or_insert(Instant::now() + pong_timeout);
+        }
+        _ = async {
+          match pong_deadline {
+            Some(deadline) => sleep_until(deadline).await,
+            None => future::pending().await,
+          }
+        } => {
+          trace!("closing websocket connection after the pong timeout");
+          break;
+        }
+      }
+    }
+    trace!("websocket connection closed");
+  }
+
+  /// Handling the client command. Returns the immediate reply if any, facts
+  /// are delivered through the outgoing queue.
+  fn handle_command(&mut self, text: &str) -> Option<ServerMessage> {
+    let command = match serde_json::from_str::<ClientCommand>(text) {
+      Ok(command) => command,
+      Err(err) => {
+        return Some(ServerMessage::Error {
+          message: format!("invalid command: {}", err),
+        })
+      }
+    };
+    trace!("handling websocket command: {:?}", command);
+
+    match command {
+      ClientCommand::Get { animal } => {
+        let Ok(permit) = self.pending_gets.clone().try_acquire_owned() else {
+          return Some(ServerMessage::Error {
+            message: format!(
+              "pending requests limit exceeded: {} at most",
+              self.server_config.websocket.max_pending_gets
+            ),
+          });
+        };
+        let fact_resolver = self.fact_resolver.clone();
+        let outgoing = self.outgoing.clone();
+        tokio::spawn(async move {
+          let _permit = permit;
+          let result = match animal {
+            Some(animal) => fact_resolver.get_animal_fact(&animal).await,
+            None => fact_resolver.get_fact().await,
+          };
+          let _ = outgoing.send(result.into()).await;
+        });
+        None
+      }
+      ClientCommand::Subscribe { animals, interval } => {
+        Some(self.subscribe(animals, interval))
+      }
+      ClientCommand::Unsubscribe { animals } => {
+        let animals = if animals.is_empty() {
+          self.subscriptions.keys().cloned().collect()
+        } else {
+          animals
+        };
+        for animal in &animals {
+          if let Some(forwarder) = self.subscriptions.remove(animal) {
+            forwarder.abort();
+          }
+        }
+        Some(ServerMessage::Unsubscribed { animals })
+      }
+    }
+  }
+
+  /// Subscribing the connection to the animals facts. Existing subscriptions
+  /// to the same animals are replaced with the new interval.
+  fn subscribe(
+    &mut self,
+    animals: Vec<String>,
+    interval: Option<u64>,
+  ) -> ServerMessage {
+    let configured = self.fact_resolver.animals();
+    if let Some(animal) = animals.iter().find(|a| !configured.contains(a)) {
+      return ServerMessage::Error {
+        message: format!("unknown animal type: {}", animal),
+      };
+    }
+
+    let new_subscriptions = animals
+      .iter()
+      .filter(|animal| !self.subscriptions.contains_key(*animal))
+      .count();
+    let max_subscriptions = self.server_config.websocket.max_subscriptions;
+    if self.subscriptions.len() + new_subscriptions > max_subscriptions {
+      return ServerMessage::Error {
+        message: format!(
+          "subscriptions limit exceeded: {} animals at most",
+          max_subscriptions
+        ),
+      };
+    }
+
+    let interval = stream_interval(&self.server_config.stream, interval);
+    for animal in &animals {
+      let mut receiver = self.fact_hub.subscribe(animal, interval);
+      let outgoing = self.outgoing.clone();
+      let forwarder = tokio::spawn(async move {
+        loop {
+          match receiver.recv().await {
+            Ok(message) => {
+              if outgoing.send(message).await.is_err() {
+                break;
+              }
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+          }
+        }
+      });
+      if let Some(previous) =
+        self.subscriptions.insert(animal.clone(), forwarder)
+      {
+        previous.abort();
+      }
+    }
+
+    ServerMessage::Subscribed {
+      animals,
+      interval: interval.as_secs(),
+    }
+  }
+}
+
+impl Drop for Connection {
+  fn drop(&mut self) {
+    for forwarder in self.subscriptions.values() {
+      forwarder.abort();
+    }
+  }
+}
+
+/// Serializing and sending the message to the client
+async fn send(
+  socket: &mut WebSocket,
+  message: &ServerMessage,
+) -> Result<(), axum::Error> {
+  let text =
+    serde_json::to_string(message).expect("server message serialization error");
+  socket.send(Message::Text(text)).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::WebSocketConfig;
+  use crate::handlers::app;
+  use crate::test_util::{dog_resolver, DOG_FACT};
+  use futures::{SinkExt, StreamExt};
+  use mockito::Server;
+  use std::net::{SocketAddr, TcpListener};
+  use tokio::time::timeout_at;
+  use tokio_tungstenite::{connect_async, tungstenite};
+
+  /// Spawning a real server listening on any available port
+  fn spawn_server(
+    fact_resolver: FactResolver,
+    server_config: &ServerConfig,
+  ) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let app = app(fact_resolver, server_config);
+    tokio::spawn(async move {
+      axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+    });
+    address
+  }
+
+  /// Sending the command and waiting for the next server message
+  async fn request<S>(client: &mut S, command: &ClientCommand) -> ServerMessage
+  where
+    S: SinkExt<tungstenite::Message>
+      + StreamExt<Item = tungstenite::Result<tungstenite::Message>>
+      + Unpin,
+    <S as futures::Sink<tungstenite::Message>>::Error: std::fmt::Debug,
+  {
+    let command = serde_json::to_string(command).unwrap();
+    client
+      .send(tungstenite::Message::Text(command))
+      .await
+      .unwrap();
+    loop {
+      match client.next().await.unwrap().unwrap() {
+        tungstenite::Message::Text(text) => {
+          return serde_json::from_str(&text).unwrap()
+        }
+        _ => continue,
+      }
+    }
+  }
+
+  /// Requesting a single fact by the `get` command
+  #[tokio::test]
+  async fn get_command() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, mock) = dog_resolver(&mut server, Some(1)).await;
+    let address = spawn_server(fact_resolver, &ServerConfig::default());
+
+    let (mut client, _) =
+      connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    let message =
+      request(&mut client, &ClientCommand::Get { animal: None }).await;
+
+    match message {
+      ServerMessage::Fact(response) => {
+        assert_eq!(response.animal, "dog", "animal type is not as expected");
+        assert_eq!(response.fact, DOG_FACT, "facts are different");
+      }
+      _ => panic!("fact message expected"),
+    }
+    mock.assert();
+  }
+
+  /// Subscribing to the animal and receiving the fact
+  #[tokio::test]
+  async fn subscribe_command() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, mock) = dog_resolver(&mut server, Some(1)).await;
+    let address = spawn_server(fact_resolver, &ServerConfig::default());
+
+    let (mut client, _) =
+      connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    let subscribe = ClientCommand::Subscribe {
+      animals: vec!["dog".into()],
+      interval: Some(60),
+    };
+    match request(&mut client, &subscribe).await {
+      ServerMessage::Subscribed { animals, interval } => {
+        assert_eq!(animals, vec!["dog".to_string()]);
+        assert_eq!(interval, 60);
+      }
+      _ => panic!("subscribed message expected"),
+    }
+
+    let message = loop {
+      if let tungstenite::Message::Text(text) =
+        client.next().await.unwrap().unwrap()
+      {
+        break serde_json::from_str::<ServerMessage>(&text).unwrap();
+      }
+    };
+    assert!(matches!(message, ServerMessage::Fact(_)), "fact expected");
+    mock.assert();
+  }
+
+  /// Requesting more facts at the same time than allowed per connection
+  #[tokio::test]
+  async fn pending_gets_limit() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, mock) = dog_resolver(&mut server, Some(0)).await;
+    let server_config = ServerConfig {
+      websocket: WebSocketConfig {
+        max_pending_gets: 0,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let address = spawn_server(fact_resolver, &server_config);
+
+    let (mut client, _) =
+      connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    let message =
+      request(&mut client, &ClientCommand::Get { animal: None }).await;
+
+    match message {
+      ServerMessage::Error { message } => {
+        assert!(message.contains("limit exceeded"), "{}", message)
+      }
+      _ => panic!("error message expected"),
+    }
+    mock.assert();
+  }
+
+  /// Subscribing to more animals than allowed per connection
+  #[tokio::test]
+  async fn subscriptions_limit() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, _mock) = dog_resolver(&mut server, Some(0)).await;
+    let server_config = ServerConfig {
+      websocket: WebSocketConfig {
+        max_subscriptions: 0,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let address = spawn_server(fact_resolver, &server_config);
+
+    let (mut client, _) =
+      connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    let subscribe = ClientCommand::Subscribe {
+      animals: vec!["dog".into()],
+      interval: None,
+    };
+
+    assert!(
+      matches!(
+        request(&mut client, &subscribe).await,
+        ServerMessage::Error { .. }
+      ),
+      "error message expected"
+    );
+  }
+
+  /// Client answering the pings should stay connected even when the pong
+  /// timeout is shorter than the ping interval
+  #[tokio::test]
+  async fn answered_pings() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, mock) = dog_resolver(&mut server, Some(1)).await;
+    let server_config = ServerConfig {
+      websocket: WebSocketConfig {
+        ping_interval: 2,
+        pong_timeout: 1,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let address = spawn_server(fact_resolver, &server_config);
+
+    let (mut client, _) =
+      connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    // Reading answers the pings until the first one is long overdue
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while let Ok(message) = timeout_at(deadline, client.next()).await {
+      assert!(
+        matches!(message, Some(Ok(tungstenite::Message::Ping(_)))),
+        "only pings expected: {:?}",
+        message
+      );
+    }
+    let message =
+      request(&mut client, &ClientCommand::Get { animal: None }).await;
+
+    assert!(matches!(message, ServerMessage::Fact(_)), "fact expected");
+    mock.assert();
+  }
+
+  /// Subscribers of the same animal and interval should share one upstream
+  /// request per tick
+  #[tokio::test]
+  async fn shared_fan_out() {
+    let mut server = Server::new_async().await;
+    let (fact_resolver, mock) = dog_resolver(&mut server, Some(1)).await;
+    let fact_hub = FactHub::new(fact_resolver);
+
+    let mut first = fact_hub.subscribe("dog", Duration::from_secs(60));
+    let mut second = fact_hub.subscribe("dog", Duration::from_secs(60));
+    assert_eq!(fact_hub.channels.lock().unwrap().len(), 1);
+
+    for receiver in [&mut first, &mut second] {
+      assert!(
+        matches!(receiver.recv().await.unwrap(), ServerMessage::Fact(_)),
+        "fact expected"
+      );
+    }
+    mock.assert();
+  }
+}
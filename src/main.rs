@@ -4,13 +4,17 @@
 
 #![deny(missing_docs)]
 
+use anyhow::Context;
 use axum::Server;
-use std::env;
+use std::{env, sync::Arc};
 use tracing::{info, instrument, trace, warn};
 
 mod config;
 mod facts;
+mod grpc;
 mod handlers;
+#[cfg(test)]
+mod test_util;
 
 #[tokio::main]
 #[instrument]
@@ -24,20 +28,42 @@ async fn main() {
     .expect("configuration file loading error");
 
   trace!("constructing fact resolver");
-  let fact_resolver = facts::FactResolver::new(&confg_data.animals);
+  let mut fact_resolver = facts::FactResolver::new(&confg_data.animals);
+  if let Some(store_config) = &confg_data.animals.store {
+    trace!("opening fact store: {}", store_config.path);
+    let store = facts::store::FactStore::open(&store_config.path)
+      .expect("fact store opening error");
+    fact_resolver = fact_resolver.with_store(store);
+  }
+
+  let grpc_server = async {
+    if confg_data.server.grpc.enabled {
+      grpc::serve(fact_resolver.clone(), Arc::new(confg_data.server.clone()))
+        .await
+        .context("grpc server error")?;
+    }
+    anyhow::Ok(())
+  };
 
   info!(
     "server is starting to listen on {}",
     confg_data.server.address
   );
-  Server::bind(
+  let http_server = Server::bind(
     &confg_data
       .server
       .address
       .parse()
       .expect("failed to parse listening address value"),
   )
-  .serve(handlers::app(fact_resolver).into_make_service())
-  .await
-  .expect("failed to start axum web server")
+  .serve(
+    handlers::app(fact_resolver.clone(), &confg_data.server)
+      .into_make_service(),
+  );
+  let http_server =
+    async { http_server.await.context("axum web server error") };
+
+  // Either server failing stops the process instead of leaving the other one
+  // running alone
+  tokio::try_join!(http_server, grpc_server).expect("server error");
 }
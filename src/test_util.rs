@@ -0,0 +1,40 @@
+//! Fixtures shared by the tests of the transports
+
+use crate::facts::adapters::dog::DogFactEndpointResponse;
+use crate::facts::config::FactsConfig;
+use crate::facts::FactResolver;
+use mockito::{Mock, Server};
+use std::collections::HashMap;
+
+/// Fact served by the mocked dog facts endpoint
+pub const DOG_FACT: &str = "some funny dog fact";
+
+/// Creating the fact resolver of the `dog` default animal with the mocked dog
+/// facts endpoint, expecting the number of the endpoint hits when given
+pub async fn dog_resolver(
+  server: &mut Server,
+  hits: Option<usize>,
+) -> (FactResolver, Mock) {
+  let dog_fact_response = DogFactEndpointResponse {
+    facts: vec![DOG_FACT.to_string()],
+    success: true,
+  };
+  let mut mock = server
+    .mock("GET", "/somefacts")
+    .with_body(serde_json::to_string(&dog_fact_response).unwrap());
+  if let Some(hits) = hits {
+    mock = mock.expect(hits);
+  }
+  let mock = mock.create_async().await;
+  let facts_config = dog_config(&(server.url() + "/somefacts"));
+  (FactResolver::new(&facts_config), mock)
+}
+
+/// Facts configuration of the `dog` default animal served by the endpoint
+pub fn dog_config(dog_fact_url: &str) -> FactsConfig {
+  FactsConfig {
+    default: "dog".into(),
+    facts: HashMap::from([("dog".to_string(), dog_fact_url.into())]),
+    ..Default::default()
+  }
+}